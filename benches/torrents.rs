@@ -0,0 +1,113 @@
+//! Mide cuantos announces por segundo se atienden desde varios threads a la vez, con todos los torrents
+//! detras de un unico lock (un solo shard, como antes) y repartidos en shards.
+//!
+//! Se corre con `cargo bench`.
+
+use std::{collections::HashMap, net::SocketAddr, sync::Arc, thread, time::Instant};
+
+use web_rust::tracker::{
+    config::TrackerConfig,
+    data::{
+        constants::{ID_LEN, TORRENT_SHARDS},
+        ids::{InfoHash, PeerId},
+        peer_info::{Event, PeerInfo},
+        sharded_torrents::ShardedTorrents,
+        torrent_info::TorrentInfo,
+    },
+    metrics::Metrics,
+    registry::get_or_register_torrent,
+};
+
+const THREADS: usize = 8;
+const ANNOUNCES_PER_THREAD: usize = 20_000;
+const TORRENTS: usize = 256;
+const PEERS_PER_TORRENT: usize = 50;
+
+fn get_info_hash(torrent: usize) -> InfoHash {
+    let mut info_hash = [0; ID_LEN];
+    info_hash[..8].copy_from_slice(&(torrent as u64).to_be_bytes());
+    InfoHash::from_bytes(&info_hash).unwrap()
+}
+
+fn get_peer(info_hash: InfoHash, peer: usize) -> PeerInfo {
+    let mut peer_id = [b'-'; ID_LEN];
+    peer_id[..8].copy_from_slice(&(peer as u64).to_be_bytes());
+    let sock_addr: SocketAddr = format!("10.0.{}.{}:6881", peer / 250, peer % 250 + 1)
+        .parse()
+        .unwrap();
+    PeerInfo::new_from_udp(
+        info_hash,
+        PeerId::from_bytes(&peer_id).unwrap(),
+        6881,
+        0,
+        0,
+        (peer % 2) as u64,
+        Some(Event::Started),
+        None,
+        sock_addr,
+    )
+    .unwrap()
+}
+
+//Hace los announces de todos los threads y devuelve cuantos se atendieron por segundo
+fn run_announces(number_of_shards: usize, config: &Arc<TrackerConfig>) -> f64 {
+    let torrents: HashMap<InfoHash, TorrentInfo> = (0..TORRENTS)
+        .map(|torrent| {
+            let info_hash = get_info_hash(torrent);
+            (info_hash, TorrentInfo::new(info_hash, config))
+        })
+        .collect();
+    let torrents = Arc::new(ShardedTorrents::new(
+        number_of_shards,
+        torrents,
+        Arc::new(Metrics::new()),
+    ));
+
+    let start = Instant::now();
+    let handles: Vec<_> = (0..THREADS)
+        .map(|thread_number| {
+            let torrents = Arc::clone(&torrents);
+            let config = Arc::clone(config);
+            thread::spawn(move || {
+                for announce in 0..ANNOUNCES_PER_THREAD {
+                    let number = thread_number * ANNOUNCES_PER_THREAD + announce;
+                    let info_hash = get_info_hash(number % TORRENTS);
+                    let peer_info = get_peer(info_hash, number % PEERS_PER_TORRENT);
+                    let mut shard = torrents.write_shard(&info_hash).unwrap();
+                    if let Some(torrent) = get_or_register_torrent(&mut shard, &peer_info, &config)
+                    {
+                        let response = torrent.get_bencoded_response_for_announce(&peer_info);
+                        torrent.add_peer(peer_info.get_peer_id(), peer_info);
+                        assert!(!response.is_empty());
+                    }
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    (THREADS * ANNOUNCES_PER_THREAD) as f64 / start.elapsed().as_secs_f64()
+}
+
+fn main() {
+    let config = Arc::new(TrackerConfig::default());
+    //Una primera pasada para que las mediciones no incluyan el calentamiento
+    run_announces(TORRENT_SHARDS, &config);
+
+    let single_lock = run_announces(1, &config);
+    let sharded = run_announces(TORRENT_SHARDS, &config);
+    println!(
+        "{} threads, {} torrents, {} announces",
+        THREADS,
+        TORRENTS,
+        THREADS * ANNOUNCES_PER_THREAD
+    );
+    println!("single lock: {:>12.0} announces/s", single_lock);
+    println!(
+        "{} shards:   {:>12.0} announces/s ({:.2}x)",
+        TORRENT_SHARDS,
+        sharded,
+        sharded / single_lock
+    );
+}
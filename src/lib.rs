@@ -29,23 +29,50 @@ pub mod tracker;
 
 use std::{
     collections::HashMap,
+    env,
     error::Error,
     fmt,
-    net::TcpListener,
-    sync::{Arc, RwLock},
+    net::{SocketAddr, TcpListener, UdpSocket},
+    sync::Arc,
     thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
-use log::info;
+use log::{error, info, warn};
+use signal_hook::{
+    consts::{SIGINT, SIGTERM},
+    iterator::{Handle, Signals},
+};
 
-use tracker::{communication, data::torrent_info::TorrentInfo};
+use tracker::{
+    communication,
+    config::TrackerConfig,
+    data::{constants::*, sharded_torrents::ShardedTorrents},
+    metrics::Metrics,
+    registry,
+    shutdown::GlobalShutdown,
+    snapshot,
+    stats::{Stats, SwarmSample},
+    thread_pool::ThreadPoolError,
+};
 
-type ArcMutexOfTorrents = Arc<RwLock<HashMap<Vec<u8>, TorrentInfo>>>;
+type ArcMutexOfTorrents = Arc<ShardedTorrents>;
 type ResultDyn<T> = Result<T, Box<dyn Error>>;
 
 #[derive(Debug)]
 pub enum TrackerError {
     UnlockingMutexOfTorrents,
+    NoAddressToListen,
+    TorrentWithoutInfo,
+}
+
+///Devuelve el codigo de salida del programa segun el error con el que termino: 2 si el tracker se cerro sin
+/// que terminaran todos los requests en curso, 1 para cualquier otro error.
+pub fn get_exit_code(error: &(dyn Error + 'static)) -> u8 {
+    match error.downcast_ref::<ThreadPoolError>() {
+        Some(ThreadPoolError::ShutdownTimeout) => EXIT_CODE_SHUTDOWN_TIMEOUT,
+        _ => EXIT_CODE_ERROR,
+    }
 }
 
 impl fmt::Display for TrackerError {
@@ -56,46 +83,196 @@ impl fmt::Display for TrackerError {
 
 impl Error for TrackerError {}
 
-fn init_torrents() -> ArcMutexOfTorrents {
-    //Creo un torrent generico para hacer pruebas
-    let mut dic_torrents = HashMap::new();
-    dic_torrents.insert(
-        "abcdefghijklmn123456".as_bytes().to_vec(),
-        TorrentInfo::new("abcdefghijklmn123456".as_bytes().to_vec()),
-    );
-    // ...
-    // ...
+//Restaura los torrents del ultimo snapshot (si lo hay) y luego los actualiza segun el registro actual
+fn init_torrents(config: &TrackerConfig, metrics: Arc<Metrics>) -> ResultDyn<ArcMutexOfTorrents> {
+    let restored = snapshot::load_snapshot(config).unwrap_or_else(|error| {
+        error!("Could not restore snapshot: {}", error);
+        HashMap::new()
+    });
+    //Diccionario de TorrentInfo repartido en shards, cada uno con su propio RwLock
+    let mutex_of_torrents = Arc::new(ShardedTorrents::new(TORRENT_SHARDS, restored, metrics));
+    registry::update_torrents(&mutex_of_torrents, registry::load_registry(config)?, config)?;
+    Ok(mutex_of_torrents)
+}
+
+//Vuelve a leer periodicamente el registro de torrents para poder agregar o quitar torrents sin reiniciar
+fn init_registry_rescan(
+    mutex_of_torrents: ArcMutexOfTorrents,
+    global_shutdown: Arc<GlobalShutdown>,
+    config: Arc<TrackerConfig>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut last_scan = Instant::now();
+        while config.registry_rescan_secs > 0 && !global_shutdown.is_set() {
+            if global_shutdown.wait_timeout(Duration::from_secs(1)) {
+                break;
+            }
+            if last_scan.elapsed() < Duration::from_secs(config.registry_rescan_secs) {
+                continue;
+            }
+            last_scan = Instant::now();
+            //Si el registro no se pudo leer completo se mantienen los torrents hasta el proximo intento
+            let registry = match registry::load_registry(&config) {
+                Ok(registry) => registry,
+                Err(error) => {
+                    error!("Skipping registry rescan: {}", error);
+                    continue;
+                }
+            };
+            if let Err(error) = registry::update_torrents(&mutex_of_torrents, registry, &config) {
+                error!("{}", error);
+            }
+        }
+    })
+}
+
+//Guarda periodicamente el estado de los swarms para no perderlo si el tracker se cierra inesperadamente
+fn init_snapshots(
+    mutex_of_torrents: ArcMutexOfTorrents,
+    global_shutdown: Arc<GlobalShutdown>,
+    config: Arc<TrackerConfig>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut last_snapshot = Instant::now();
+        while config.snapshot_interval_secs > 0 && !global_shutdown.is_set() {
+            if global_shutdown.wait_timeout(Duration::from_secs(1)) {
+                break;
+            }
+            if last_snapshot.elapsed() < Duration::from_secs(config.snapshot_interval_secs) {
+                continue;
+            }
+            last_snapshot = Instant::now();
+            if let Err(error) = snapshot::save_snapshot(&mutex_of_torrents, &config) {
+                error!("Could not save snapshot: {}", error);
+            }
+        }
+    })
+}
 
-    //RwLock de un diccionario que contiene los TorrentInfo
-    Arc::new(RwLock::new(dic_torrents))
+//Cada cierto tiempo registra en las estadisticas la cantidad de torrents, seeders y leechers
+fn init_stats_sampler(
+    mutex_of_torrents: ArcMutexOfTorrents,
+    global_shutdown: Arc<GlobalShutdown>,
+    stats: Arc<Stats>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut last_sample: Option<Instant> = None;
+        while !global_shutdown.is_set() {
+            if let Some(last_sample) = last_sample {
+                if last_sample.elapsed() < Duration::from_secs(STATS_SAMPLE_SECS) {
+                    global_shutdown.wait_timeout(Duration::from_secs(1));
+                    continue;
+                }
+            }
+            last_sample = Some(Instant::now());
+            match SwarmSample::from_torrents(&mutex_of_torrents) {
+                Ok(sample) => stats.set_swarms(snapshot::get_unix_time(), sample),
+                Err(error) => error!("{}", error),
+            }
+        }
+    })
 }
 
-fn init_handler_for_quit_input(global_shutdown: Arc<RwLock<bool>>) -> JoinHandle<()> {
+//El hilo queda bloqueado leyendo stdin, por lo que no se espera a que termine al cerrar el tracker
+fn init_handler_for_quit_input(global_shutdown: Arc<GlobalShutdown>) {
     let exit_command = String::from("q\n");
     info!("Waiting for input");
     thread::spawn(move || loop {
         let mut command = String::new();
-        let _ = std::io::stdin().read_line(&mut command);
+        //Sin stdin (por ejemplo corriendo como servicio o en Docker) solo se puede cerrar con una señal
+        match std::io::stdin().read_line(&mut command) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => (),
+        }
         if command == exit_command {
             info!("Executing quit command");
-            let _ = set_global_shutdown(&global_shutdown); // Revisar que hacer con el error que surge de aca.
+            global_shutdown.set();
             break;
         }
+    });
+}
+
+//Activa el shutdown global al recibir SIGINT o SIGTERM. El Handle permite terminar el hilo si el tracker
+//se cierra por otro motivo.
+fn init_signal_handler(
+    global_shutdown: Arc<GlobalShutdown>,
+) -> ResultDyn<(Handle, JoinHandle<()>)> {
+    let mut signals = Signals::new([SIGINT, SIGTERM])?;
+    let handle = signals.handle();
+    let join_handle = thread::spawn(move || {
+        if let Some(signal) = signals.forever().next() {
+            info!("Received signal {}, shutting down", signal);
+            global_shutdown.set();
+        }
+    });
+    Ok((handle, join_handle))
+}
+
+//Cada cierto tiempo elimina de los swarms a los peers que dejaron de anunciar, y a los torrents agregados
+//en modo abierto que se quedaron sin peers
+fn init_peers_reaper(
+    mutex_of_torrents: ArcMutexOfTorrents,
+    global_shutdown: Arc<GlobalShutdown>,
+    config: Arc<TrackerConfig>,
+) -> JoinHandle<()> {
+    let expiry = Duration::from_secs(config.get_peer_expiry_secs());
+    thread::spawn(move || {
+        let mut last_reap = Instant::now();
+        while !global_shutdown.is_set() {
+            if global_shutdown.wait_timeout(Duration::from_secs(1)) {
+                break;
+            }
+            if last_reap.elapsed() < Duration::from_secs(REAPER_PERIOD_SECS) {
+                continue;
+            }
+            last_reap = Instant::now();
+            let mut removed = 0;
+            let reaped = mutex_of_torrents.retain(|_, torrent| {
+                removed += torrent.remove_expired_peers(expiry);
+                true
+            });
+            if let Err(error) = reaped {
+                error!("{}", error);
+                continue;
+            }
+            if removed > 0 {
+                info!("Removed {} expired peers", removed);
+            }
+            match registry::remove_empty_unregistered_torrents(&mutex_of_torrents) {
+                Ok(removed) if removed > 0 => info!("Removed {} empty torrents", removed),
+                Ok(_) => (),
+                Err(error) => error!("{}", error),
+            }
+        }
     })
 }
 
-fn is_global_shutdown_set(global_shutdown: &Arc<RwLock<bool>>) -> bool {
-    if let Ok(mutex_sutdown) = global_shutdown.read() {
-        *mutex_sutdown
-    } else {
-        true // Si el global shutdown está poisoned, hay que cortar todo igual
-    }
+//Bindea el listener TCP (HTTP) y el socket UDP en la misma direccion
+fn bind_listeners(address: SocketAddr) -> ResultDyn<(TcpListener, UdpSocket)> {
+    let listener = TcpListener::bind(address)?;
+    let udp_socket = UdpSocket::bind(address)?;
+    Ok((listener, udp_socket))
 }
 
-fn set_global_shutdown(global_shutdown: &Arc<RwLock<bool>>) -> ResultDyn<()> {
-    let mut global_shutdown = global_shutdown.write().map_err(|err| format!("{}", err))?;
-    *global_shutdown = true;
-    Ok(())
+//El tracker UDP comparte el diccionario de torrents con el HTTP para que los peers se vean entre si
+fn init_udp_tracker(
+    udp_socket: UdpSocket,
+    mutex_of_torrents: ArcMutexOfTorrents,
+    global_shutdown: Arc<GlobalShutdown>,
+    config: Arc<TrackerConfig>,
+    stats: Arc<Stats>,
+    metrics: Arc<Metrics>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        communication::udp_handler::general_communication_udp(
+            udp_socket,
+            mutex_of_torrents,
+            global_shutdown,
+            config,
+            stats,
+            metrics,
+        )
+    })
 }
 
 ///
@@ -112,19 +289,98 @@ pub fn run() -> ResultDyn<()> {
     pretty_env_logger::init();
     info!("tracker init");
 
-    let global_shutdown = Arc::new(RwLock::new(false));
+    let config = Arc::new(TrackerConfig::load(env::args().skip(1), env::vars())?);
 
-    let mutex_of_torrents: ArcMutexOfTorrents = init_torrents();
+    let global_shutdown = Arc::new(GlobalShutdown::new());
 
-    let join_hander = init_handler_for_quit_input(Arc::clone(&global_shutdown));
+    //Las metricas se crean antes que los torrents para medir todo lo que se espera por sus locks
+    let metrics = Arc::new(Metrics::new());
+    let mutex_of_torrents: ArcMutexOfTorrents = init_torrents(&config, Arc::clone(&metrics))?;
 
+    //Los listeners se bindean antes de levantar los hilos, asi si no se puede escuchar en ninguna direccion
+    //no queda ningun hilo que esperar
     // Nota (Miguel): Por las dudas al pasarlo al otro lado, despues usar el try bind del tp viejo.
-    let listener = TcpListener::bind("127.0.0.1:7878")?;
-    let _ = listener.set_nonblocking(true);
+    let mut listeners = vec![];
+    let mut udp_sockets = vec![];
+    for address in config.listen_addresses.iter().copied() {
+        //Si la maquina no soporta alguna de las versiones de IP se sigue con las restantes
+        match bind_listeners(address) {
+            Ok((listener, udp_socket)) => {
+                listeners.push(listener);
+                udp_sockets.push(udp_socket);
+            }
+            Err(error) => warn!("Could not listen on {}: {}", address, error),
+        }
+    }
+    if listeners.is_empty() {
+        return Err(Box::new(TrackerError::NoAddressToListen));
+    }
+
+    init_handler_for_quit_input(Arc::clone(&global_shutdown));
+    let (signals_handle, signals_join_handler) = init_signal_handler(Arc::clone(&global_shutdown))?;
+    let reaper_join_handler = init_peers_reaper(
+        Arc::clone(&mutex_of_torrents),
+        Arc::clone(&global_shutdown),
+        Arc::clone(&config),
+    );
+    let rescan_join_handler = init_registry_rescan(
+        Arc::clone(&mutex_of_torrents),
+        Arc::clone(&global_shutdown),
+        Arc::clone(&config),
+    );
+    let snapshots_join_handler = init_snapshots(
+        Arc::clone(&mutex_of_torrents),
+        Arc::clone(&global_shutdown),
+        Arc::clone(&config),
+    );
+    let stats = Arc::new(Stats::new());
+    let stats_join_handler = init_stats_sampler(
+        Arc::clone(&mutex_of_torrents),
+        Arc::clone(&global_shutdown),
+        Arc::clone(&stats),
+    );
+    let udp_join_handlers: Vec<JoinHandle<()>> = udp_sockets
+        .into_iter()
+        .map(|udp_socket| {
+            init_udp_tracker(
+                udp_socket,
+                Arc::clone(&mutex_of_torrents),
+                Arc::clone(&global_shutdown),
+                Arc::clone(&config),
+                Arc::clone(&stats),
+                Arc::clone(&metrics),
+            )
+        })
+        .collect();
+
     info!("Listening...");
-    communication::handler::general_communication(listener, mutex_of_torrents, global_shutdown);
+    let drained = communication::handler::general_communication(
+        listeners,
+        Arc::clone(&mutex_of_torrents),
+        Arc::clone(&global_shutdown),
+        Arc::clone(&config),
+        stats,
+        metrics,
+    );
+    //Si el loop de eventos termino por un error el resto de los hilos tambien tiene que terminar
+    global_shutdown.set();
 
-    let _ = join_hander.join();
+    for udp_join_handler in udp_join_handlers {
+        let _ = udp_join_handler.join();
+    }
+    let _ = reaper_join_handler.join();
+    let _ = rescan_join_handler.join();
+    let _ = snapshots_join_handler.join();
+    let _ = stats_join_handler.join();
+    signals_handle.close();
+    let _ = signals_join_handler.join();
+
+    //Con todos los requests terminados se guarda el estado final de los swarms
+    if let Err(error) = snapshot::save_snapshot(&mutex_of_torrents, &config) {
+        error!("Could not save snapshot: {}", error);
+    }
 
+    drained?;
+    info!("Tracker closed");
     Ok(())
 }
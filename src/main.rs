@@ -1,6 +1,12 @@
-use std::error::Error;
-use web_rust::run;
+use std::process::ExitCode;
+use web_rust::{get_exit_code, run};
 
-fn main() -> Result<(), Box<dyn Error>> {
-    run()
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            ExitCode::from(get_exit_code(error.as_ref()))
+        }
+    }
 }
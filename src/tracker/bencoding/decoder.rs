@@ -1,17 +1,19 @@
 //!# Modulo de decoder de Bencoding
 //! Este modulo va a servir para pasar a String/Integer/List/Dic dado un String
-//!  que esta en el formato Bencoding
+//!  que esta en el formato Bencoding. El [Decoder] recorre los bytes una sola vez sin copiarlos,
+//!  devolviendo tokens con slices prestados del original, y las funciones de este modulo arman los
+//!  valores a partir de esos tokens.
 
 use super::constants::*;
 use super::values::*;
-use std::collections::HashMap;
+use std::{collections::HashMap, ops::Range};
 
 type DicValues = HashMap<Vec<u8>, ValuesBencoding>;
-type TupleStringRest = (Vec<u8>, Vec<u8>);
-type TupleIntegerRest = (i64, Vec<u8>);
-type TupleListRest = (Vec<ValuesBencoding>, Vec<u8>);
-type TupleValueRest = (ValuesBencoding, Vec<u8>);
-type TupleDicRest = (DicValues, Vec<u8>);
+type TupleStringRest<'a> = (&'a [u8], &'a [u8]);
+type TupleIntegerRest<'a> = (i64, &'a [u8]);
+type TupleListRest<'a> = (Vec<ValuesBencoding>, &'a [u8]);
+type TupleDicRest<'a> = (DicValues, &'a [u8]);
+type DicSpans = HashMap<Vec<u8>, Range<usize>>;
 
 const NEGATIVE_ZERO: &str = "-0";
 const MINUS: char = '-';
@@ -19,46 +21,177 @@ const ZERO: char = '0';
 
 type ResultBencoding<T> = Result<T, ErrorBencoding>;
 
-///Funcion que dado un String en formato bencoding va a desencodear a String y luego va a devolver un Result
-/// de una tupla con el String desencodeado y lo que sobre del String pasado, o en caso de error se devolvera
-/// el mismo que sera del tipo ErrorBencoding, por ej: en caso de pasar "4:testi32e3:fin" se devolvera Ok con la tupla
-/// ("test", "i32e3:fin")
-fn to_string(to_parse: Vec<u8>) -> ResultBencoding<TupleStringRest> {
-    let mut result = vec![];
-    let mut long_string = String::new();
-    let mut valid_format = false;
-
-    //Tomo todos los valores antes del ':' que deberian representar el largo del string
-    let mut list_chars = to_parse.into_iter();
-    for long_char in list_chars.by_ref() {
-        if long_char == TWO_POINTS {
-            valid_format = true;
-            break;
-        }
-        long_string.push(long_char as char);
+///Cada una de las partes del bencoding que va devolviendo el [Decoder]. Los strings y las claves son
+/// slices de los bytes originales.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Token<'a> {
+    String(&'a [u8]),
+    Integer(i64),
+    ListStart,
+    DicStart,
+    ///Clave de un diccionario, el siguiente token es su valor
+    Key(&'a [u8]),
+    ///Cierre de la ultima lista o diccionario abierto
+    End,
+}
+
+//Listas y diccionarios abiertos, en los diccionarios se guarda si ya se leyo la clave de la entrada actual
+#[derive(PartialEq, Debug)]
+enum Container {
+    List,
+    Dic { key_read: bool },
+}
+
+///Decoder de bencoding sobre un slice de bytes, que lo recorre de principio a fin sin volver atras
+pub struct Decoder<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    open: Vec<Container>,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Decoder {
+            bytes,
+            position: 0,
+            open: vec![],
+        }
     }
 
-    //Valido que haya pasado por un ':' al recorrer el string
-    if !valid_format {
-        return Err(ErrorBencoding::String(ErrorType::Format));
+    ///Devuelve la posicion (en bytes desde el inicio) del proximo token
+    pub fn get_position(&self) -> usize {
+        self.position
     }
 
-    //Parseo el numero en string pasandolo a u32
-    let long_int = match long_string.parse::<u32>() {
-        Ok(number) => number,
-        Err(_) => return Err(ErrorBencoding::String(ErrorType::Format)),
-    };
+    ///Devuelve lo que falta leer
+    pub fn get_rest(&self) -> &'a [u8] {
+        &self.bytes[self.position..]
+    }
 
-    //Voy concatenando caracter a caracter formando el string con la longitud que tome anteriormente
-    for _ in 0..long_int {
-        if let Some(char_string) = list_chars.next() {
-            result.push(char_string);
-        } else {
-            return Err(ErrorBencoding::String(ErrorType::Long));
+    ///Devuelve la cantidad de listas y diccionarios abiertos
+    pub fn get_depth(&self) -> usize {
+        self.open.len()
+    }
+
+    //El error de formato depende de si se estaba leyendo una lista o un diccionario
+    fn get_format_error(&self) -> ErrorBencoding {
+        match self.open.last() {
+            Some(Container::List) => ErrorBencoding::List(ErrorType::Format),
+            _ => ErrorBencoding::Dic(ErrorType::Format),
+        }
+    }
+
+    //Si se termino de leer el valor de una entrada de un diccionario, lo siguiente es otra clave
+    fn set_value_read(&mut self) {
+        if let Some(Container::Dic { key_read }) = self.open.last_mut() {
+            *key_read = false;
+        }
+    }
+
+    //Lee un string con el formato "<largo>:<contenido>"
+    fn read_string(&mut self) -> ResultBencoding<&'a [u8]> {
+        let rest = self.get_rest();
+        //Tomo todos los valores antes del ':' que deberian representar el largo del string
+        let two_points = match rest.iter().position(|byte| *byte == TWO_POINTS) {
+            Some(position) => position,
+            None => return Err(ErrorBencoding::String(ErrorType::Format)),
+        };
+        let long_string: String = rest[..two_points].iter().map(|c| *c as char).collect();
+        let long_int = match long_string.parse::<u32>() {
+            Ok(number) => number as usize,
+            Err(_) => return Err(ErrorBencoding::String(ErrorType::Format)),
+        };
+
+        let start = two_points + 1;
+        match rest.get(start..start + long_int) {
+            Some(string) => {
+                self.position += start + long_int;
+                Ok(string)
+            }
+            None => Err(ErrorBencoding::String(ErrorType::Long)),
         }
     }
 
-    Ok((result, list_chars.collect()))
+    //Lee un entero con el formato "i<numero>e"
+    fn read_integer(&mut self) -> ResultBencoding<i64> {
+        let rest = self.get_rest();
+        //Valido que el primer caracter sea 'i'
+        if rest.first() != Some(&CHAR_I) {
+            return Err(ErrorBencoding::Integer(ErrorType::Format));
+        }
+        //Valido que termine en 'e'
+        let end = match rest.iter().position(|byte| *byte == CHAR_E) {
+            Some(position) => position,
+            None => return Err(ErrorBencoding::Integer(ErrorType::Format)),
+        };
+        let num_str: String = rest[1..end].iter().map(|c| *c as char).collect();
+        //Valido que el valor del numero sea valido
+        if !is_valid_number(num_str.clone()) {
+            return Err(ErrorBencoding::Integer(ErrorType::Number));
+        }
+        match num_str.parse::<i64>() {
+            Ok(num) => {
+                self.position += end + 1;
+                Ok(num)
+            }
+            Err(_) => Err(ErrorBencoding::Integer(ErrorType::Number)),
+        }
+    }
+
+    ///Lee el siguiente token. Devuelve error si el formato es invalido o si los bytes terminan antes de
+    /// cerrar todas las listas y diccionarios abiertos.
+    pub fn next_token(&mut self) -> ResultBencoding<Token<'a>> {
+        let type_char = match self.bytes.get(self.position) {
+            Some(type_char) => *type_char,
+            None => return Err(self.get_format_error()),
+        };
+        let expecting_key = self.open.last() == Some(&Container::Dic { key_read: false });
+
+        //Una lista se puede cerrar en cualquier momento y un diccionario solo cuando se espera una clave
+        if type_char == CHAR_E && (expecting_key || self.open.last() == Some(&Container::List)) {
+            self.position += 1;
+            self.open.pop();
+            self.set_value_read();
+            return Ok(Token::End);
+        }
+        if expecting_key {
+            let key = self
+                .read_string()
+                .map_err(|_| ErrorBencoding::Dic(ErrorType::Format))?;
+            self.open.pop();
+            self.open.push(Container::Dic { key_read: true });
+            return Ok(Token::Key(key));
+        }
+
+        let token = if type_char.is_ascii_digit() {
+            Token::String(self.read_string()?)
+        } else if type_char == CHAR_I {
+            Token::Integer(self.read_integer()?)
+        } else if type_char == CHAR_L {
+            self.position += 1;
+            self.open.push(Container::List);
+            return Ok(Token::ListStart);
+        } else if type_char == CHAR_D {
+            self.position += 1;
+            self.open.push(Container::Dic { key_read: false });
+            return Ok(Token::DicStart);
+        } else {
+            return Err(self.get_format_error());
+        };
+        self.set_value_read();
+        Ok(token)
+    }
+
+    ///Saltea el valor completo que empieza en la posicion actual (con todo lo que tenga adentro si es una
+    /// lista o un diccionario) y devuelve su primer token
+    pub fn skip_value(&mut self) -> ResultBencoding<Token<'a>> {
+        let depth = self.get_depth();
+        let token = self.next_token()?;
+        while self.get_depth() > depth {
+            self.next_token()?;
+        }
+        Ok(token)
+    }
 }
 
 fn is_valid_number(num: String) -> bool {
@@ -77,145 +210,122 @@ fn is_valid_number(num: String) -> bool {
     true
 }
 
-///Funcion que va a pasar un String en formato bencoding a un i64, los cual va a devolverlos en un Result, con el
-/// formato de una tupla, la cual su primer valor sera el i64 y el siguiente el resto del string del bencoding pasado,
-/// en caso de error se devolvera el mismo
-fn to_integer(to_parse: Vec<u8>) -> ResultBencoding<TupleIntegerRest> {
-    let mut num_str = String::new();
-    let mut valid_format = false;
-    let mut list_chars = to_parse.into_iter();
-
-    //Valido que el primer caracter sea 'i'
-    if let Some(CHAR_I) = list_chars.next() {
-    } else {
-        return Err(ErrorBencoding::Integer(ErrorType::Format));
-    }
-
-    for num_char in list_chars.by_ref() {
-        if num_char == CHAR_E {
-            valid_format = true;
-            break;
+//Arma el valor que empieza con el token dado, leyendo del decoder el resto de sus tokens
+fn to_value(decoder: &mut Decoder, token: Token) -> ResultBencoding<ValuesBencoding> {
+    match token {
+        Token::String(string) => Ok(ValuesBencoding::String(string.to_vec())),
+        Token::Integer(int) => Ok(ValuesBencoding::Integer(int)),
+        Token::ListStart => {
+            let mut list = vec![];
+            loop {
+                match decoder.next_token()? {
+                    Token::End => return Ok(ValuesBencoding::List(list)),
+                    token => list.push(to_value(decoder, token)?),
+                }
+            }
         }
-        num_str.push(num_char as char);
-    }
-
-    //Valido que haya terminado en 'e'
-    if !valid_format {
-        return Err(ErrorBencoding::Integer(ErrorType::Format));
-    }
-    //Valido que el valor del numero sea valido
-    if !is_valid_number(num_str.clone()) {
-        return Err(ErrorBencoding::Integer(ErrorType::Number));
+        Token::DicStart => {
+            let mut dic = HashMap::new();
+            loop {
+                match decoder.next_token()? {
+                    Token::End => return Ok(ValuesBencoding::Dic(dic)),
+                    Token::Key(key) => {
+                        let token = decoder.next_token()?;
+                        dic.insert(key.to_vec(), to_value(decoder, token)?);
+                    }
+                    _ => return Err(ErrorBencoding::Dic(ErrorType::Format)),
+                }
+            }
+        }
+        Token::Key(_) | Token::End => Err(ErrorBencoding::Dic(ErrorType::Format)),
     }
+}
 
-    match num_str.parse::<i64>() {
-        Ok(num) => Ok((num, list_chars.collect())),
-        Err(_) => Err(ErrorBencoding::Integer(ErrorType::Number)),
-    }
+///Funcion que dado un String en formato bencoding va a desencodear a String y luego va a devolver un Result
+/// de una tupla con el String desencodeado y lo que sobre del String pasado, o en caso de error se devolvera
+/// el mismo que sera del tipo ErrorBencoding, por ej: en caso de pasar "4:testi32e3:fin" se devolvera Ok con la tupla
+/// ("test", "i32e3:fin")
+pub fn to_string(to_parse: &[u8]) -> ResultBencoding<TupleStringRest<'_>> {
+    let mut decoder = Decoder::new(to_parse);
+    let string = decoder.read_string()?;
+    Ok((string, decoder.get_rest()))
 }
 
-fn take_value_by_type(
-    from: u8,
-    type_char: u8,
-    to_parse: Vec<u8>,
-) -> ResultBencoding<TupleValueRest> {
-    if type_char.is_ascii_digit() {
-        let (str, next_parse) = to_string(to_parse)?;
-        Ok((ValuesBencoding::String(str), next_parse))
-    } else if type_char == CHAR_I {
-        let (int, next_parse) = to_integer(to_parse)?;
-        Ok((ValuesBencoding::Integer(int), next_parse))
-    } else if type_char == CHAR_L {
-        let (list, next_parse) = to_list(to_parse)?;
-        Ok((ValuesBencoding::List(list), next_parse))
-    } else if type_char == CHAR_D {
-        let (dic, next_parse) = to_dic(to_parse)?;
-        Ok((ValuesBencoding::Dic(dic), next_parse))
-    } else if from == CHAR_L {
-        Err(ErrorBencoding::List(ErrorType::Format))
-    } else {
-        Err(ErrorBencoding::Dic(ErrorType::Format))
-    }
+///Funcion que va a pasar un String en formato bencoding a un i64, los cual va a devolverlos en un Result, con el
+/// formato de una tupla, la cual su primer valor sera el i64 y el siguiente el resto del string del bencoding pasado,
+/// en caso de error se devolvera el mismo
+pub fn to_integer(to_parse: &[u8]) -> ResultBencoding<TupleIntegerRest<'_>> {
+    let mut decoder = Decoder::new(to_parse);
+    let int = decoder.read_integer()?;
+    Ok((int, decoder.get_rest()))
 }
 
 ///Funcion que va a desencodear un String del tipo Bencoding en una lista ([Vec]), la cual sera devuelta en un Result con
 /// el formato de una tupla en la cual su primer valor sera la lista desencodeada y su segundo valor sera el restante del String,
 /// en caso de error se devolvera el mismo
-fn to_list(to_parse: Vec<u8>) -> ResultBencoding<TupleListRest> {
-    let mut list_return = Vec::new();
-    let mut valid_format = false;
-    let mut list_chars = to_parse.into_iter();
-
+pub fn to_list(to_parse: &[u8]) -> ResultBencoding<TupleListRest<'_>> {
     //Reviso que el string comience con 'l'
-    match list_chars.next() {
-        Some(CHAR_L) => (),
-        _ => return Err(ErrorBencoding::List(ErrorType::Format)),
-    }
-
-    let mut to_parse: Vec<u8> = list_chars.clone().collect();
-
-    while let Some(next_char) = list_chars.next() {
-        if next_char == CHAR_E {
-            valid_format = true;
-            break;
-        }
-        let (value, next_parse) = take_value_by_type(CHAR_L, next_char, to_parse)?;
-        list_return.push(value);
-        to_parse = next_parse;
-        list_chars = to_parse.clone().into_iter();
-    }
-
-    if !valid_format {
+    if to_parse.first() != Some(&CHAR_L) {
         return Err(ErrorBencoding::List(ErrorType::Format));
     }
-
-    Ok((list_return, list_chars.collect()))
+    let mut decoder = Decoder::new(to_parse);
+    let token = decoder.next_token()?;
+    match to_value(&mut decoder, token)? {
+        ValuesBencoding::List(list) => Ok((list, decoder.get_rest())),
+        _ => Err(ErrorBencoding::List(ErrorType::Format)),
+    }
 }
 
 ///Funcion para desencodear un String del tipo bencoding en formato de diccionario ([HashMap]) en el cual se devolvera un Result,
 /// el cual contendra una tupla con el diccionario como primer valor y el sobrante del string del bencoding pasado como segundo
 /// valor, en caso de error se devolvera el correspondiente
-pub fn to_dic(to_parse: Vec<u8>) -> ResultBencoding<TupleDicRest> {
-    let mut dic_return = HashMap::new();
-    let mut valid_format = false;
-    let mut list_chars = to_parse.into_iter();
-
+pub fn to_dic(to_parse: &[u8]) -> ResultBencoding<TupleDicRest<'_>> {
     //Reviso que el string comience con 'd'
-    match list_chars.next() {
-        Some(CHAR_D) => (),
-        _ => return Err(ErrorBencoding::Dic(ErrorType::Format)),
+    if to_parse.first() != Some(&CHAR_D) {
+        return Err(ErrorBencoding::Dic(ErrorType::Format));
     }
+    let mut decoder = Decoder::new(to_parse);
+    let token = decoder.next_token()?;
+    match to_value(&mut decoder, token)? {
+        ValuesBencoding::Dic(dic) => Ok((dic, decoder.get_rest())),
+        _ => Err(ErrorBencoding::Dic(ErrorType::Format)),
+    }
+}
 
-    let mut to_parse: Vec<u8> = list_chars.clone().collect();
-
-    while let Some(next_char) = list_chars.next() {
-        if next_char == CHAR_E {
-            valid_format = true;
-            break;
-        }
-        let (key, next_parse) = match to_string(to_parse.clone()) {
-            Ok((k, p)) => (k, p),
-            Err(_) => return Err(ErrorBencoding::Dic(ErrorType::Format)),
-        };
-        if let Some(char_next) = next_parse.clone().into_iter().next() {
-            let (value, next_parse) = take_value_by_type(CHAR_D, char_next, next_parse)?;
-            dic_return.insert(key, value);
-            to_parse = next_parse;
-        } else {
-            return Err(ErrorBencoding::Dic(ErrorType::Format));
+///Igual que [from_torrent_to_dic], pero ademas devuelve en que rango de bytes del original esta el valor de
+/// cada entrada del diccionario, para poder usar su bencoding exacto (por ejemplo para calcular el info hash)
+pub fn from_torrent_to_dic_with_spans(
+    torrent_file: &[u8],
+) -> Result<(DicValues, DicSpans), ErrorBencoding> {
+    //Reviso que el string comience con 'd'
+    if torrent_file.first() != Some(&CHAR_D) {
+        return Err(ErrorBencoding::Dic(ErrorType::Format));
+    }
+    let mut decoder = Decoder::new(torrent_file);
+    decoder.next_token()?;
+
+    let mut dic = HashMap::new();
+    let mut spans = HashMap::new();
+    loop {
+        match decoder.next_token()? {
+            Token::End => break,
+            Token::Key(key) => {
+                let start = decoder.get_position();
+                let token = decoder.next_token()?;
+                dic.insert(key.to_vec(), to_value(&mut decoder, token)?);
+                spans.insert(key.to_vec(), start..decoder.get_position());
+            }
+            _ => return Err(ErrorBencoding::Dic(ErrorType::Format)),
         }
-
-        list_chars = to_parse.clone().into_iter();
     }
 
-    if !valid_format {
+    if !decoder.get_rest().is_empty() {
         return Err(ErrorBencoding::Dic(ErrorType::Format));
     }
-
-    Ok((dic_return, list_chars.collect()))
+    Ok((dic, spans))
 }
 
-pub fn from_torrent_to_dic(torrent_file: Vec<u8>) -> Result<DicValues, ErrorBencoding> {
+pub fn from_torrent_to_dic(torrent_file: &[u8]) -> Result<DicValues, ErrorBencoding> {
     match to_dic(torrent_file) {
         Ok((result, rest)) => {
             if rest.is_empty() {
@@ -238,10 +348,10 @@ mod tests {
             let bencoding_string = String::from("3:exe");
             let bencoding_bytes = bencoding_string.as_bytes().to_vec();
             let return_str = String::from("exe").as_bytes().to_vec();
-            let return_rest = vec![];
+            let return_rest: Vec<u8> = vec![];
 
-            let result = to_string(bencoding_bytes);
-            assert_eq!(result, Ok((return_str, return_rest)));
+            let result = to_string(&bencoding_bytes);
+            assert_eq!(result, Ok((&return_str[..], &return_rest[..])));
         }
         #[test]
         fn to_string_ok_rest_valid() {
@@ -250,23 +360,23 @@ mod tests {
             let return_str = "magic".as_bytes().to_vec();
             let return_rest = "4:testi32e".as_bytes().to_vec();
 
-            let result = to_string(bencoding_bytes);
+            let result = to_string(&bencoding_bytes);
 
-            assert_eq!(result, Ok((return_str, return_rest)));
+            assert_eq!(result, Ok((&return_str[..], &return_rest[..])));
 
             let return_str = "test".as_bytes().to_vec();
             let return_rest = "i32e".as_bytes().to_vec();
 
             if let Ok((_, rest)) = result {
                 let result = to_string(rest);
-                assert_eq!(result, Ok((return_str, return_rest)));
+                assert_eq!(result, Ok((&return_str[..], &return_rest[..])));
             }
         }
         #[test]
         fn to_string_error_format() {
             let bencoding = "4exe".as_bytes().to_vec();
             assert_eq!(
-                to_string(bencoding),
+                to_string(&bencoding),
                 Err(ErrorBencoding::String(ErrorType::Format))
             );
         }
@@ -274,7 +384,7 @@ mod tests {
         fn to_string_error_without_number() {
             let bencoding = "test".as_bytes().to_vec();
             assert_eq!(
-                to_string(bencoding),
+                to_string(&bencoding),
                 Err(ErrorBencoding::String(ErrorType::Format))
             );
         }
@@ -282,7 +392,7 @@ mod tests {
         fn to_string_error_invalid_number() {
             let bencoding = "a:test".as_bytes().to_vec();
             assert_eq!(
-                to_string(bencoding),
+                to_string(&bencoding),
                 Err(ErrorBencoding::String(ErrorType::Format))
             );
         }
@@ -290,7 +400,7 @@ mod tests {
         fn to_string_error_invalid_long() {
             let bencoding = "12:test".as_bytes().to_vec();
             assert_eq!(
-                to_string(bencoding),
+                to_string(&bencoding),
                 Err(ErrorBencoding::String(ErrorType::Long))
             );
         }
@@ -301,19 +411,19 @@ mod tests {
         fn to_integer_ok_positive() {
             let bencoding_int = "i32e".as_bytes().to_vec();
             let return_int = 32;
-            let return_rest = vec![];
+            let return_rest: Vec<u8> = vec![];
 
-            let result = to_integer(bencoding_int);
-            assert_eq!(result, Ok((return_int, return_rest)));
+            let result = to_integer(&bencoding_int);
+            assert_eq!(result, Ok((return_int, &return_rest[..])));
         }
         #[test]
         fn to_integer_ok_negative() {
             let bencoding_int = "i-320e".as_bytes().to_vec();
             let return_int = -320;
-            let return_rest = vec![];
+            let return_rest: Vec<u8> = vec![];
 
-            let result = to_integer(bencoding_int);
-            assert_eq!(result, Ok((return_int, return_rest)));
+            let result = to_integer(&bencoding_int);
+            assert_eq!(result, Ok((return_int, &return_rest[..])));
         }
         #[test]
         fn to_integer_ok_rest_valid() {
@@ -321,28 +431,28 @@ mod tests {
             let return_int = 32;
             let return_rest = "i-200e4:test".as_bytes().to_vec();
 
-            let result = to_integer(bencoding_int);
-            assert_eq!(result, Ok((return_int, return_rest)));
+            let result = to_integer(&bencoding_int);
+            assert_eq!(result, Ok((return_int, &return_rest[..])));
 
             let return_int = -200;
             let return_rest = "4:test".as_bytes().to_vec();
 
             if let Ok((_, rest)) = result {
                 let result = to_integer(rest);
-                assert_eq!(result, Ok((return_int, return_rest)))
+                assert_eq!(result, Ok((return_int, &return_rest[..])))
             }
         }
         #[test]
         fn to_integer_error_format() {
             let bencoding_int = "32e".as_bytes().to_vec();
             assert_eq!(
-                to_integer(bencoding_int),
+                to_integer(&bencoding_int),
                 Err(ErrorBencoding::Integer(ErrorType::Format))
             );
 
             let bencoding_int = "i32".as_bytes().to_vec();
             assert_eq!(
-                to_integer(bencoding_int),
+                to_integer(&bencoding_int),
                 Err(ErrorBencoding::Integer(ErrorType::Format))
             );
         }
@@ -350,7 +460,7 @@ mod tests {
         fn to_integer_error_minus_zero() {
             let bencoding_int = "i-0e".as_bytes().to_vec();
             assert_eq!(
-                to_integer(bencoding_int),
+                to_integer(&bencoding_int),
                 Err(ErrorBencoding::Integer(ErrorType::Number))
             );
         }
@@ -358,13 +468,13 @@ mod tests {
         fn to_integer_error_zero_and_number() {
             let bencoding_int = "i018e".as_bytes().to_vec();
             assert_eq!(
-                to_integer(bencoding_int),
+                to_integer(&bencoding_int),
                 Err(ErrorBencoding::Integer(ErrorType::Number))
             );
 
             let bencoding_int = "i-08e".as_bytes().to_vec();
             assert_eq!(
-                to_integer(bencoding_int),
+                to_integer(&bencoding_int),
                 Err(ErrorBencoding::Integer(ErrorType::Number))
             );
         }
@@ -372,7 +482,7 @@ mod tests {
         fn to_integer_error_invalid_number() {
             let bencoding_int = "i2a3e".as_bytes().to_vec();
             assert_eq!(
-                to_integer(bencoding_int),
+                to_integer(&bencoding_int),
                 Err(ErrorBencoding::Integer(ErrorType::Number))
             );
         }
@@ -384,9 +494,9 @@ mod tests {
             let str_expected = ValuesBencoding::String("test".as_bytes().to_vec());
             let int_expected = ValuesBencoding::Integer(32);
             let rest_expected = "3:exe".as_bytes().to_vec();
-            let result_expected = (vec![str_expected, int_expected], rest_expected);
+            let result_expected = (vec![str_expected, int_expected], &rest_expected[..]);
             let to_parse = "l4:testi32ee3:exe".as_bytes().to_vec();
-            let result = to_list(to_parse);
+            let result = to_list(&to_parse);
             assert_eq!(result, Ok(result_expected));
         }
         #[test]
@@ -395,22 +505,22 @@ mod tests {
             let int_expected = ValuesBencoding::Integer(32);
             let vec_expected = ValuesBencoding::List(vec![str_expected, int_expected]);
             let rest_expected = "3:exe".as_bytes().to_vec();
-            let result_expected = (vec![vec_expected], rest_expected);
+            let result_expected = (vec![vec_expected], &rest_expected[..]);
             let to_parse = "ll4:testi32eee3:exe".as_bytes().to_vec();
-            let result = to_list(to_parse);
+            let result = to_list(&to_parse);
             assert_eq!(result, Ok(result_expected));
         }
         #[test]
         fn to_list_error_format() {
             let to_parse = "4:testi32ee3:exe".as_bytes().to_vec();
             assert_eq!(
-                to_list(to_parse),
+                to_list(&to_parse),
                 Err(ErrorBencoding::List(ErrorType::Format))
             );
 
             let to_parse = "la:testi32ee3:exe".as_bytes().to_vec();
             assert_eq!(
-                to_list(to_parse),
+                to_list(&to_parse),
                 Err(ErrorBencoding::List(ErrorType::Format))
             );
         }
@@ -418,7 +528,7 @@ mod tests {
         fn to_list_error_not_close() {
             let to_parse = "l4:testi32e3:exe".as_bytes().to_vec();
             assert_eq!(
-                to_list(to_parse),
+                to_list(&to_parse),
                 Err(ErrorBencoding::List(ErrorType::Format))
             );
         }
@@ -426,13 +536,13 @@ mod tests {
         fn to_list_error_string() {
             let to_parse = "l4teste".as_bytes().to_vec();
             assert_eq!(
-                to_list(to_parse),
+                to_list(&to_parse),
                 Err(ErrorBencoding::String(ErrorType::Format))
             );
 
             let to_parse = "l10:teste".as_bytes().to_vec();
             assert_eq!(
-                to_list(to_parse),
+                to_list(&to_parse),
                 Err(ErrorBencoding::String(ErrorType::Long))
             );
         }
@@ -440,19 +550,19 @@ mod tests {
         fn to_list_error_integer() {
             let to_parse = "li-0ee".as_bytes().to_vec();
             assert_eq!(
-                to_list(to_parse),
+                to_list(&to_parse),
                 Err(ErrorBencoding::Integer(ErrorType::Number))
             );
 
             let to_parse = "li032ee".as_bytes().to_vec();
             assert_eq!(
-                to_list(to_parse),
+                to_list(&to_parse),
                 Err(ErrorBencoding::Integer(ErrorType::Number))
             );
 
             let to_parse = "li5".as_bytes().to_vec();
             assert_eq!(
-                to_list(to_parse),
+                to_list(&to_parse),
                 Err(ErrorBencoding::Integer(ErrorType::Format))
             );
         }
@@ -470,7 +580,7 @@ mod tests {
             );
             let rest_expected = "3:exe".as_bytes().to_vec();
 
-            assert_eq!(Ok((dic_expected, rest_expected)), to_dic(bencoding));
+            assert_eq!(Ok((dic_expected, &rest_expected[..])), to_dic(&bencoding));
         }
         #[test]
         fn to_dic_create_with_list_inside_ok() {
@@ -491,7 +601,7 @@ mod tests {
             );
             let rest_expected = "3:exe".as_bytes().to_vec();
 
-            assert_eq!(Ok((dic_expected, rest_expected)), to_dic(bencoding));
+            assert_eq!(Ok((dic_expected, &rest_expected[..])), to_dic(&bencoding));
         }
         #[test]
         fn to_dic_create_with_dic_inside_ok() {
@@ -508,7 +618,7 @@ mod tests {
             );
             let rest_expected = "3:exe".as_bytes().to_vec();
 
-            assert_eq!(Ok((dic_expected, rest_expected)), to_dic(bencoding));
+            assert_eq!(Ok((dic_expected, &rest_expected[..])), to_dic(&bencoding));
         }
         #[test]
         fn to_dic_create_complex_ok() {
@@ -579,26 +689,26 @@ mod tests {
                 ValuesBencoding::List(list_dic),
             );
 
-            assert_eq!(Ok((dic_expected, vec![])), to_dic(bencoding))
+            assert_eq!(Ok((dic_expected, &[][..])), to_dic(&bencoding))
         }
         #[test]
         fn to_dic_invalid_format() {
             let bencoding = "8:announcei32e4:test3:exee3:exe".as_bytes().to_vec();
             assert_eq!(
                 Err(ErrorBencoding::Dic(ErrorType::Format)),
-                to_dic(bencoding)
+                to_dic(&bencoding)
             );
 
             let bencoding = "d8:announcei32e4:test3:exe3:exe".as_bytes().to_vec();
             assert_eq!(
                 Err(ErrorBencoding::Dic(ErrorType::Format)),
-                to_dic(bencoding)
+                to_dic(&bencoding)
             );
 
             let bencoding = "d8:announcei32e4:test3:exe".as_bytes().to_vec();
             assert_eq!(
                 Err(ErrorBencoding::Dic(ErrorType::Format)),
-                to_dic(bencoding)
+                to_dic(&bencoding)
             );
         }
         #[test]
@@ -606,7 +716,7 @@ mod tests {
             let bencoding = "di0ei32e4:test3:exee3:exe".as_bytes().to_vec();
             assert_eq!(
                 Err(ErrorBencoding::Dic(ErrorType::Format)),
-                to_dic(bencoding)
+                to_dic(&bencoding)
             );
         }
         #[test]
@@ -614,7 +724,7 @@ mod tests {
             let bencoding = "d8:announcei-0e4:test3:exee3:exe".as_bytes().to_vec();
             assert_eq!(
                 Err(ErrorBencoding::Integer(ErrorType::Number)),
-                to_dic(bencoding)
+                to_dic(&bencoding)
             );
         }
         #[test]
@@ -622,19 +732,19 @@ mod tests {
             let bencoding = "d8:announcei32e4:testl2:el".as_bytes().to_vec();
             assert_eq!(
                 Err(ErrorBencoding::List(ErrorType::Format)),
-                to_dic(bencoding)
+                to_dic(&bencoding)
             );
 
             let bencoding = "d8:announcei32e4:testlf:ele".as_bytes().to_vec();
             assert_eq!(
                 Err(ErrorBencoding::List(ErrorType::Format)),
-                to_dic(bencoding)
+                to_dic(&bencoding)
             );
 
             let bencoding = "d8:announcei32e4:testl2:eli-0eee".as_bytes().to_vec();
             assert_eq!(
                 Err(ErrorBencoding::Integer(ErrorType::Number)),
-                to_dic(bencoding)
+                to_dic(&bencoding)
             );
         }
         #[test]
@@ -642,20 +752,105 @@ mod tests {
             let bencoding = "d8:announcei32e4:testdi32ee".as_bytes().to_vec();
             assert_eq!(
                 Err(ErrorBencoding::Dic(ErrorType::Format)),
-                to_dic(bencoding)
+                to_dic(&bencoding)
             );
 
             let bencoding = "d8:announcei32e4:testd3:exei-12e".as_bytes().to_vec();
             assert_eq!(
                 Err(ErrorBencoding::Dic(ErrorType::Format)),
-                to_dic(bencoding)
+                to_dic(&bencoding)
             );
 
             let bencoding = "d8:announcei32e3:inid4:testi-0ee".as_bytes().to_vec();
             assert_eq!(
                 Err(ErrorBencoding::Integer(ErrorType::Number)),
-                to_dic(bencoding)
+                to_dic(&bencoding)
             );
         }
     }
+    mod tests_decoder {
+        use super::*;
+        #[test]
+        fn tokens_borrow_from_input_ok() {
+            let bencoding = "d4:listli1e3:abce3:numi-5ee".as_bytes();
+            let mut decoder = Decoder::new(bencoding);
+            let mut tokens = vec![];
+            while let Ok(token) = decoder.next_token() {
+                tokens.push(token);
+                if decoder.get_depth() == 0 {
+                    break;
+                }
+            }
+            assert_eq!(
+                vec![
+                    Token::DicStart,
+                    Token::Key(b"list"),
+                    Token::ListStart,
+                    Token::Integer(1),
+                    Token::String(b"abc"),
+                    Token::End,
+                    Token::Key(b"num"),
+                    Token::Integer(-5),
+                    Token::End,
+                ],
+                tokens
+            );
+            assert_eq!(bencoding.len(), decoder.get_position());
+
+            //Los strings apuntan a los bytes originales, sin copiarlos
+            let mut decoder = Decoder::new(bencoding);
+            decoder.next_token().unwrap();
+            if let Ok(Token::Key(key)) = decoder.next_token() {
+                assert_eq!(bencoding[3..].as_ptr(), key.as_ptr());
+            } else {
+                panic!("expected a key");
+            }
+        }
+        #[test]
+        fn skip_value_moves_to_next_value_ok() {
+            let bencoding = "ld1:ali1ei2eee3:fine".as_bytes();
+            let mut decoder = Decoder::new(bencoding);
+            decoder.next_token().unwrap();
+            assert_eq!(Ok(Token::DicStart), decoder.skip_value());
+            assert_eq!(14, decoder.get_position());
+            assert_eq!(Ok(Token::String(b"fin")), decoder.next_token());
+            assert_eq!(Ok(Token::End), decoder.next_token());
+            assert_eq!(
+                Err(ErrorBencoding::Dic(ErrorType::Format)),
+                Decoder::new(b"d1:a").skip_value()
+            );
+        }
+        #[test]
+        fn dic_with_spans_ok() {
+            let bencoding = "d4:infod4:name1:b6:lengthi5ee3:numi-5ee".as_bytes();
+            let (dic, spans) = from_torrent_to_dic_with_spans(bencoding).unwrap();
+
+            assert_eq!(
+                Some(&ValuesBencoding::Integer(-5)),
+                dic.get(b"num".as_slice())
+            );
+            assert_eq!(
+                b"d4:name1:b6:lengthi5ee",
+                &bencoding[spans[b"info".as_slice()].clone()]
+            );
+            assert_eq!(b"i-5e", &bencoding[spans[b"num".as_slice()].clone()]);
+            assert_eq!(
+                Err(ErrorBencoding::Dic(ErrorType::Format)),
+                from_torrent_to_dic_with_spans(b"d3:numi1ee3:exe")
+            );
+        }
+        #[test]
+        fn large_list_ok() {
+            let elements = 200_000;
+            let mut bencoding = vec![CHAR_L];
+            for _ in 0..elements {
+                bencoding.extend_from_slice(b"i7e3:abc");
+            }
+            bencoding.push(CHAR_E);
+
+            let (list, rest) = to_list(&bencoding).unwrap();
+            assert_eq!(2 * elements, list.len());
+            assert!(rest.is_empty());
+        }
+    }
 }
@@ -1,135 +1,929 @@
 use std::{
+    collections::HashMap,
     fs,
     io::{ErrorKind, Read, Write},
-    net::{SocketAddr, TcpListener, TcpStream},
-    sync::{Arc, RwLock},
+    net::{SocketAddr, TcpListener},
+    path::Path,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use log::{error, info};
+use log::{error, info, warn};
+use mio::{
+    net::{TcpListener as MioTcpListener, TcpStream},
+    Events, Interest, Poll, Token, Waker,
+};
 
 use crate::{
-    is_global_shutdown_set,
     tracker::{
+        bencoding::{encoder::from_dic, values::ValuesBencoding},
+        communication::request::{HttpError, HttpRequest},
+        config::{RejectionPolicy, TrackerConfig},
         data::{
             constants::*,
-            peer_info::{get_error_response_for_announce, PeerInfo, PeerInfoError},
+            peer_info::{
+                get_error_response_for_announce, get_info_hashes_for_scrape, PeerInfo,
+                PeerInfoError,
+            },
         },
-        thread_pool::ThreadPool,
+        metrics::Metrics,
+        registry::get_or_register_torrent,
+        shutdown::GlobalShutdown,
+        snapshot::get_unix_time,
+        stats::{Stats, StatsRange, SwarmSample},
+        thread_pool::{ThreadPool, ThreadPoolError},
+        urlencoding::query::QueryParams,
     },
-    ArcMutexOfTorrents, ResultDyn, TrackerError,
+    ArcMutexOfTorrents, ResultDyn,
 };
 
 fn get_response_details(
-    buffer: &[u8],
+    request: &HttpRequest,
     dic_torrents: &ArcMutexOfTorrents,
     ip_port: SocketAddr,
+    config: &TrackerConfig,
+    stats: &Stats,
+    metrics: &Metrics,
 ) -> ResultDyn<Vec<u8>> {
-    let info_of_announced_peer = PeerInfo::new((*buffer).to_vec(), ip_port);
+    let get_error_response = |error: PeerInfoError| {
+        metrics.add_announce_error(&error);
+        get_error_response_for_announce(error).as_bytes().to_vec()
+    };
+    let info_of_announced_peer =
+        PeerInfo::new(request, ip_port).and_then(|peer| peer.check_port_allowed(config));
 
     let details = match info_of_announced_peer {
         Ok(info_of_announced_peer) => {
             let info_hash = info_of_announced_peer.get_info_hash();
-            match dic_torrents.write() {
-                Ok(mut unlocked_dic) => match unlocked_dic.get_mut(&info_hash) {
-                    Some(torrent) => {
-                        let response = torrent.get_bencoded_response_for_announce(
-                            info_of_announced_peer.get_peer_id(),
-                            info_of_announced_peer.is_compact(),
-                        );
-                        torrent
-                            .add_peer(info_of_announced_peer.get_peer_id(), info_of_announced_peer);
-                        response
+            match dic_torrents.write_shard(&info_hash) {
+                Ok(mut shard) => {
+                    match get_or_register_torrent(&mut shard, &info_of_announced_peer, config) {
+                        Some(torrent) => match torrent
+                            .check_announce_interval(&info_of_announced_peer)
+                        {
+                            Ok(()) => {
+                                let response = torrent
+                                    .get_bencoded_response_for_announce(&info_of_announced_peer);
+                                let delta = torrent.add_peer(
+                                    info_of_announced_peer.get_peer_id(),
+                                    info_of_announced_peer,
+                                );
+                                stats.add_announce(get_unix_time(), delta);
+                                response
+                            }
+                            Err(error) => get_error_response(error),
+                        },
+                        None => get_error_response(PeerInfoError::InfoHashInvalid),
                     }
-                    None => get_error_response_for_announce(PeerInfoError::InfoHashInvalid)
-                        .as_bytes()
-                        .to_vec(),
-                },
-                Err(_) => return Err(Box::new(TrackerError::UnlockingMutexOfTorrents)), // Como este es error de nuestro server podriamos considerar cambiarlo a un error de codigo 500 por ej, sino el peer no se entera de nada y le cortamos de repente
+                }
+                Err(error) => return Err(Box::new(error)), // Como este es error de nuestro server podriamos considerar cambiarlo a un error de codigo 500 por ej, sino el peer no se entera de nada y le cortamos de repente
             }
         }
-        Err(error) => get_error_response_for_announce(error).as_bytes().to_vec(),
+        Err(error) => get_error_response(error),
     };
     Ok(details)
 }
 
-fn handle_single_connection(
-    mut stream: TcpStream,
-    dic_torrents: ArcMutexOfTorrents,
-    ip_port: SocketAddr,
-) -> ResultDyn<()> {
-    let mut buffer = [0; 1024];
-    let _ = stream.read(&mut buffer);
-    let mut status_line = OK_URL;
-
-    let mut contents = if buffer.starts_with(GET_URL) {
-        fs::read(INDEX_HTML)?
-    } else if buffer.starts_with(STATS_URL) {
-        fs::read(STATS_HTML)?
-    } else if buffer.starts_with(STYLE_URL) {
-        fs::read(STYLE_CSS)?
-    } else if buffer.starts_with(DOCS_URL) {
-        fs::read(DOCS_HTML)?
-    } else if buffer.starts_with(CODE_URL) {
-        fs::read(CODE_JS)?
-    } else if buffer.starts_with(ANNOUNCE_URL) {
-        //[TODO] Almacenar datos importantes [en .json?]
-        get_response_details(&buffer, &dic_torrents, ip_port)?
+//Arma la respuesta de un scrape con las estadisticas de los torrents pedidos, en caso de no pedir
+//ninguno se devuelven las de todos los torrents (full scrape). Los info_hash desconocidos se ignoran.
+fn get_response_scrape(
+    request: &HttpRequest,
+    dic_torrents: &ArcMutexOfTorrents,
+) -> ResultDyn<Vec<u8>> {
+    let info_hashes = match get_info_hashes_for_scrape(request) {
+        Ok(info_hashes) => info_hashes,
+        Err(error) => return Ok(get_error_response_for_announce(error).as_bytes().to_vec()),
+    };
+    let mut dic_files: HashMap<Vec<u8>, ValuesBencoding> = HashMap::new();
+
+    if info_hashes.is_empty() {
+        dic_torrents.for_each_shard(|shard| {
+            for (info_hash, torrent) in shard.iter() {
+                dic_files.insert(info_hash.as_bytes().to_vec(), torrent.get_scrape_stats());
+            }
+        })?;
     } else {
-        status_line = ERR_URL;
-        fs::read(ERROR_HTML)?
+        for info_hash in info_hashes {
+            let shard = dic_torrents.read_shard(&info_hash)?;
+            if let Some(torrent) = shard.get(&info_hash) {
+                dic_files.insert(info_hash.as_bytes().to_vec(), torrent.get_scrape_stats());
+            }
+        }
+    }
+
+    let mut dic_to_bencode = HashMap::new();
+    dic_to_bencode.insert(FILES_BYTES.to_vec(), ValuesBencoding::Dic(dic_files));
+    Ok(from_dic(dic_to_bencode))
+}
+
+fn get_static_file(path: &str) -> Option<&'static str> {
+    match path {
+        ROOT_PATH | INDEX_PATH => Some(INDEX_HTML),
+        STATS_PATH => Some(STATS_HTML),
+        STYLE_PATH => Some(STYLE_CSS),
+        DOCS_PATH => Some(DOCS_HTML),
+        CODE_PATH | CODE_JS_PATH => Some(CODE_JS),
+        _ => None,
+    }
+}
+
+//Devuelve en JSON las estadisticas del periodo pedido con `range`, por defecto la ultima hora
+fn get_response_stats(request: &HttpRequest, stats: &Stats) -> (&'static str, Vec<u8>) {
+    let range = match QueryParams::parse(request.get_query()) {
+        Ok(params) => match params.get(RANGE) {
+            Some(value) => StatsRange::parse(value),
+            None => Some(StatsRange::LastHour),
+        },
+        Err(_) => None,
+    };
+    match range {
+        Some(range) => (OK_URL, stats.get_json(range, get_unix_time()).into_bytes()),
+        None => (BAD_REQUEST_URL, ERROR_STATS_RANGE.as_bytes().to_vec()),
+    }
+}
+
+//Devuelve las metricas del tracker en el formato de texto de Prometheus
+fn get_response_metrics(
+    dic_torrents: &ArcMutexOfTorrents,
+    metrics: &Metrics,
+) -> ResultDyn<Vec<u8>> {
+    let swarms = SwarmSample::from_torrents(dic_torrents)?;
+    Ok(metrics.get_text(swarms).into_bytes())
+}
+
+//Devuelve el nombre con el que se cuentan en las metricas los requests al path dado. Los archivos estaticos
+//se agrupan en una sola ruta para no tener una por cada path que se pida.
+fn get_route_name(path: &str) -> &'static str {
+    match path {
+        ANNOUNCE_PATH => ANNOUNCE_PATH,
+        SCRAPE_PATH => SCRAPE_PATH,
+        STATS_DATA_PATH => STATS_DATA_PATH,
+        METRICS_PATH => METRICS_PATH,
+        path if get_static_file(path).is_some() => STATIC_ROUTE,
+        _ => NOT_FOUND_ROUTE,
+    }
+}
+
+//Devuelve la status line y el contenido de la respuesta al request
+fn route_request(
+    request: &HttpRequest,
+    dic_torrents: &ArcMutexOfTorrents,
+    ip_port: SocketAddr,
+    config: &TrackerConfig,
+    stats: &Stats,
+    metrics: &Metrics,
+) -> ResultDyn<(&'static str, Vec<u8>)> {
+    if request.get_method() != GET_METHOD {
+        return Ok((NOT_ALLOWED_URL, vec![]));
+    }
+    let response = match request.get_path() {
+        ANNOUNCE_PATH => (
+            OK_URL,
+            get_response_details(request, dic_torrents, ip_port, config, stats, metrics)?,
+        ),
+        STATS_DATA_PATH => get_response_stats(request, stats),
+        METRICS_PATH => (OK_URL, get_response_metrics(dic_torrents, metrics)?),
+        SCRAPE_PATH => (OK_URL, get_response_scrape(request, dic_torrents)?),
+        path => {
+            let static_dir = Path::new(&config.static_dir);
+            match get_static_file(path) {
+                Some(file) => (OK_URL, fs::read(static_dir.join(file))?),
+                None => (ERR_URL, fs::read(static_dir.join(ERROR_HTML))?),
+            }
+        }
     };
+    Ok(response)
+}
 
+//Arma la respuesta HTTP completa, con la status line, los headers y el contenido
+fn get_response_bytes(
+    status_line: &str,
+    mut contents: Vec<u8>,
+    keep_alive: bool,
+    content_type: Option<&str>,
+) -> Vec<u8> {
+    let connection = match keep_alive {
+        true => KEEP_ALIVE,
+        false => CLOSE,
+    };
+    let content_type = match content_type {
+        Some(content_type) => format!("Content-Type: {}\r\n", content_type),
+        None => String::new(),
+    };
     let mut response = format!(
-        "{}\r\nContent-Length: {}\r\n\r\n",
+        "{}\r\nContent-Length: {}\r\nConnection: {}\r\n{}\r\n",
         status_line,
         contents.len(),
+        connection,
+        content_type,
     )
     .as_bytes()
     .to_vec();
 
     response.append(&mut contents);
+    response
+}
 
-    stream.write_all(&response)?;
-    stream.flush()?;
-    Ok(())
+fn get_content_type(route: &str) -> Option<&'static str> {
+    match route {
+        METRICS_PATH => Some(METRICS_CONTENT_TYPE),
+        _ => None,
+    }
 }
 
-pub fn general_communication(
-    listener: TcpListener,
-    mutex_of_torrents: ArcMutexOfTorrents,
-    global_shutdown: Arc<RwLock<bool>>,
-) {
-    let pool = ThreadPool::new(4);
-
-    loop {
-        match listener.accept() {
-            //Uso accept para obtener tambien la ip y el puerto de quien se conecto con el tracker
-            Ok((stream, sock_addr)) => {
-                let dic_copy: ArcMutexOfTorrents = Arc::clone(&mutex_of_torrents);
-                info!(
-                    "Connected to  [ {} : {} ]",
-                    sock_addr.ip(),
-                    sock_addr.port()
-                );
-                pool.execute(move || {
-                    match handle_single_connection(stream, dic_copy, sock_addr) {
-                        Ok(_) => (),
-                        Err(error) => error!("{}", error), //Ver que hacer es casos de error
-                    }
-                });
+//Token con el que se despierta al loop de eventos, ya sea porque un worker termino un request o porque se
+//activo el shutdown global
+const WAKER_TOKEN: Token = Token(usize::MAX);
+
+//Respuesta que arma un worker para el request de una conexion, y que luego escribe el loop de eventos
+struct Response {
+    token: Token,
+    route: &'static str,
+    //Status line y contenido, o None si hubo un error del tracker y hay que cerrar la conexion sin responder
+    reply: Option<(&'static str, Vec<u8>)>,
+    keep_alive: bool,
+}
+
+//Estado de una conexion HTTP. Los bytes que llegan se acumulan en `pending` hasta formar un request, y
+//recien ahi se le pasa al pool, por lo que una conexion sin requests no ocupa ningun worker.
+struct Connection {
+    stream: TcpStream,
+    sock_addr: SocketAddr,
+    pending: Vec<u8>,
+    //Bytes de las respuestas que todavia no se pudieron escribir
+    output: Vec<u8>,
+    //Hay un request de la conexion en el pool. Los requests que llegan juntos (pipelining) se atienden de
+    //a uno para responderlos en orden.
+    in_flight: bool,
+    requests_served: usize,
+    //La conexion se cierra apenas se termine de escribir lo que queda en `output`
+    closing: bool,
+    //El cliente cerro su lado de la conexion
+    read_closed: bool,
+    //Desde cuando se espera un nuevo request completo. Los bytes sueltos no lo renuevan, asi un cliente
+    //que manda el request de a poco no puede mantener la conexion abierta indefinidamente.
+    waiting_since: Instant,
+    //Si se pidio que se avise cuando se pueda escribir, porque quedo parte de una respuesta sin escribir
+    writable_interest: bool,
+}
+
+//Loop de eventos que atiende todas las conexiones HTTP desde un solo hilo, usando el pool solo para
+//resolver los requests ya completos.
+struct EventLoop<'a> {
+    poll: Poll,
+    waker: Arc<Waker>,
+    listeners: Vec<MioTcpListener>,
+    connections: HashMap<Token, Connection>,
+    next_token: usize,
+    pool: &'a ThreadPool,
+    sender: Sender<Response>,
+    receiver: Receiver<Response>,
+    mutex_of_torrents: &'a ArcMutexOfTorrents,
+    global_shutdown: &'a Arc<GlobalShutdown>,
+    config: &'a Arc<TrackerConfig>,
+    stats: &'a Arc<Stats>,
+    metrics: &'a Arc<Metrics>,
+}
+
+impl<'a> EventLoop<'a> {
+    fn new(
+        listeners: Vec<TcpListener>,
+        pool: &'a ThreadPool,
+        mutex_of_torrents: &'a ArcMutexOfTorrents,
+        global_shutdown: &'a Arc<GlobalShutdown>,
+        config: &'a Arc<TrackerConfig>,
+        stats: &'a Arc<Stats>,
+        metrics: &'a Arc<Metrics>,
+    ) -> ResultDyn<Self> {
+        let poll = Poll::new()?;
+        let waker = Arc::new(Waker::new(poll.registry(), WAKER_TOKEN)?);
+        //Los listeners usan los primeros tokens y las conexiones los siguientes
+        let mut mio_listeners = vec![];
+        for (index, listener) in listeners.into_iter().enumerate() {
+            listener.set_nonblocking(true)?;
+            let mut listener = MioTcpListener::from_std(listener);
+            poll.registry()
+                .register(&mut listener, Token(index), Interest::READABLE)?;
+            mio_listeners.push(listener);
+        }
+        let (sender, receiver) = mpsc::channel();
+
+        Ok(EventLoop {
+            poll,
+            waker,
+            next_token: mio_listeners.len(),
+            listeners: mio_listeners,
+            connections: HashMap::new(),
+            pool,
+            sender,
+            receiver,
+            mutex_of_torrents,
+            global_shutdown,
+            config,
+            stats,
+            metrics,
+        })
+    }
+
+    fn accept(&mut self, index: usize) {
+        loop {
+            let (mut stream, sock_addr) = match self.listeners[index].accept() {
+                Ok(connection) => connection,
+                Err(error) if error.kind() == ErrorKind::WouldBlock => return,
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) => {
+                    error!("{}", error);
+                    return;
+                }
+            };
+            info!(
+                "Connected to  [ {} : {} ]",
+                sock_addr.ip(),
+                sock_addr.port()
+            );
+            self.stats.add_connection(get_unix_time());
+            let token = Token(self.next_token);
+            self.next_token += 1;
+            if let Err(error) =
+                self.poll
+                    .registry()
+                    .register(&mut stream, token, Interest::READABLE)
+            {
+                error!("{}", error);
+                continue;
+            }
+            self.connections.insert(
+                token,
+                Connection {
+                    stream,
+                    sock_addr,
+                    pending: vec![],
+                    output: vec![],
+                    in_flight: false,
+                    requests_served: 0,
+                    closing: false,
+                    read_closed: false,
+                    waiting_since: Instant::now(),
+                    writable_interest: false,
+                },
+            );
+        }
+    }
+
+    //Lee todo lo disponible en el socket (o hasta tener un request de tamaño maximo, el resto se lee una vez
+    //atendido ese request) y atiende el siguiente request si ya llego completo
+    fn read(&mut self, token: Token) {
+        let connection = match self.connections.get_mut(&token) {
+            Some(connection) => connection,
+            None => return,
+        };
+        let mut chunk = [0; READ_CHUNK_SIZE];
+        while !connection.read_closed && connection.pending.len() <= MAX_REQUEST_SIZE {
+            match connection.stream.read(&mut chunk) {
+                Ok(0) => connection.read_closed = true,
+                Ok(size) => connection.pending.extend_from_slice(&chunk[..size]),
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) => {
+                    info!("Connection with {} failed: {}", connection.sock_addr, error);
+                    self.close(token);
+                    return;
+                }
+            }
+        }
+        self.dispatch(token);
+    }
+
+    //Le pasa al pool el siguiente request de la conexion, si ya llego completo y no hay otro en curso
+    fn dispatch(&mut self, token: Token) {
+        let connection = match self.connections.get_mut(&token) {
+            Some(connection) if !connection.in_flight && !connection.closing => connection,
+            _ => return,
+        };
+        let request = match HttpRequest::take_from(&mut connection.pending) {
+            Ok(Some(request)) => request,
+            Ok(None) => {
+                //Si el cliente ya no va a mandar nada mas se cierra al terminar de responder
+                if connection.read_closed {
+                    connection.closing = true;
+                    self.write(token);
+                }
+                return;
             }
+            Err(HttpError::TooLarge) => {
+                return self.queue_response(token, INVALID_ROUTE, TOO_LARGE_URL, vec![], false)
+            }
+            Err(_) => {
+                return self.queue_response(token, INVALID_ROUTE, BAD_REQUEST_URL, vec![], false)
+            }
+        };
+
+        connection.requests_served += 1;
+        connection.in_flight = true;
+        let route = get_route_name(request.get_path());
+        let keep_alive = request.wants_keep_alive()
+            && connection.requests_served < self.config.max_requests_per_connection
+            && !self.global_shutdown.is_set();
+        let sock_addr = connection.sock_addr;
+        let dic_copy: ArcMutexOfTorrents = Arc::clone(self.mutex_of_torrents);
+        let config_copy = Arc::clone(self.config);
+        let stats_copy = Arc::clone(self.stats);
+        let metrics_copy = Arc::clone(self.metrics);
+        let sender = self.sender.clone();
+        let waker = Arc::clone(&self.waker);
+        let job = move || {
+            let reply = match route_request(
+                &request,
+                &dic_copy,
+                sock_addr,
+                &config_copy,
+                &stats_copy,
+                &metrics_copy,
+            ) {
+                Ok(reply) => Some(reply),
+                Err(error) => {
+                    error!("{}", error); //Ver que hacer es casos de error
+                    None
+                }
+            };
+            let response = Response {
+                token,
+                route,
+                reply,
+                keep_alive,
+            };
+            if sender.send(response).is_ok() {
+                let _ = waker.wake();
+            }
+        };
+
+        match self.pool.execute(job) {
+            Ok(()) => (),
+            Err(ThreadPoolError::QueueFull) => self.reject(token, route),
             Err(error) => {
-                if error.kind() == ErrorKind::WouldBlock {
-                    if is_global_shutdown_set(&global_shutdown) {
-                        break;
-                    };
-                    //Por cada vez que no conecto espero 1 seg a la siguiente request
-                    //Para no estar loopeando tan rapidamente y que explote la maquina.
-                    thread::sleep(Duration::from_secs(1));
+                error!("Connection from {} dropped: {}", sock_addr, error);
+                self.close(token);
+            }
+        }
+    }
+
+    //Responde a un request que se rechazo porque no hay lugar en la cola del pool para atenderlo
+    fn reject(&mut self, token: Token, route: &'static str) {
+        warn!("Thread pool queue is full: {:?}", self.pool.get_stats());
+        if let Some(connection) = self.connections.get_mut(&token) {
+            connection.in_flight = false;
+        }
+        match self.config.pool_rejection_policy {
+            RejectionPolicy::ServiceUnavailable => {
+                self.queue_response(token, route, UNAVAILABLE_URL, vec![], false)
+            }
+            RejectionPolicy::Drop => self.close(token),
+        }
+    }
+
+    //Agrega la respuesta a lo que hay que escribir en la conexion y empieza a escribirla
+    fn queue_response(
+        &mut self,
+        token: Token,
+        route: &'static str,
+        status_line: &'static str,
+        contents: Vec<u8>,
+        keep_alive: bool,
+    ) {
+        let connection = match self.connections.get_mut(&token) {
+            Some(connection) => connection,
+            None => return,
+        };
+        self.metrics.add_request(route, status_line);
+        let response =
+            get_response_bytes(status_line, contents, keep_alive, get_content_type(route));
+        connection.output.extend_from_slice(&response);
+        connection.closing |= !keep_alive;
+        connection.waiting_since = Instant::now();
+        self.write(token);
+    }
+
+    //Recibe la respuesta de un worker y sigue con el siguiente request de la conexion, si lo hay
+    fn finish_request(&mut self, response: Response) {
+        let token = response.token;
+        match self.connections.get_mut(&token) {
+            Some(connection) => connection.in_flight = false,
+            //La conexion se cerro mientras se atendia el request
+            None => return,
+        }
+        match response.reply {
+            Some((status_line, contents)) => {
+                let keep_alive = response.keep_alive && !self.global_shutdown.is_set();
+                self.queue_response(token, response.route, status_line, contents, keep_alive);
+                self.read(token);
+            }
+            None => self.close(token),
+        }
+    }
+
+    //Escribe todo lo que se pueda de las respuestas pendientes, y si no se pudo terminar pide que se avise
+    //cuando se pueda seguir escribiendo
+    fn write(&mut self, token: Token) {
+        let connection = match self.connections.get_mut(&token) {
+            Some(connection) => connection,
+            None => return,
+        };
+        while !connection.output.is_empty() {
+            match connection.stream.write(&connection.output) {
+                Ok(0) => return self.close(token),
+                Ok(size) => {
+                    connection.output.drain(..size);
                 }
+                Err(error) if error.kind() == ErrorKind::WouldBlock => break,
+                Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+                Err(error) => {
+                    info!("Connection with {} failed: {}", connection.sock_addr, error);
+                    return self.close(token);
+                }
+            }
+        }
+        if connection.output.is_empty() && connection.closing && !connection.in_flight {
+            return self.close(token);
+        }
+
+        let writable_interest = !connection.output.is_empty();
+        if writable_interest != connection.writable_interest {
+            let interest = match writable_interest {
+                true => Interest::READABLE | Interest::WRITABLE,
+                false => Interest::READABLE,
+            };
+            connection.writable_interest = writable_interest;
+            if let Err(error) =
+                self.poll
+                    .registry()
+                    .reregister(&mut connection.stream, token, interest)
+            {
+                error!("{}", error);
+                self.close(token);
+            }
+        }
+    }
+
+    fn close(&mut self, token: Token) {
+        if let Some(mut connection) = self.connections.remove(&token) {
+            let _ = self.poll.registry().deregister(&mut connection.stream);
+        }
+    }
+
+    //Cierra las conexiones que superaron el tiempo de espera sin mandar un request completo, o sin leer la
+    //respuesta que se les mando
+    fn close_idle_connections(&mut self) {
+        let keep_alive_timeout = Duration::from_secs(self.config.keep_alive_timeout_secs);
+        let idle: Vec<Token> = self
+            .connections
+            .iter()
+            .filter(|(_, connection)| {
+                !connection.in_flight && connection.waiting_since.elapsed() >= keep_alive_timeout
+            })
+            .map(|(token, _)| *token)
+            .collect();
+        for token in idle {
+            self.close(token);
+        }
+    }
+
+    //Deja de aceptar conexiones y cierra las que no tienen un request en curso. Las demas se cierran al
+    //terminar de escribir su respuesta.
+    fn start_draining(&mut self) {
+        for listener in self.listeners.iter_mut() {
+            let _ = self.poll.registry().deregister(listener);
+        }
+        self.listeners.clear();
+        let tokens: Vec<Token> = self.connections.keys().copied().collect();
+        for token in tokens {
+            if let Some(connection) = self.connections.get_mut(&token) {
+                connection.closing = true;
             }
+            self.write(token);
+        }
+    }
+
+    //Atiende los eventos hasta que se active el shutdown global y terminen los requests en curso, o se
+    //supere el tiempo limite para que terminen. Devuelve el momento en que se empezo a cerrar.
+    fn run(&mut self) -> ResultDyn<Instant> {
+        let mut events = Events::with_capacity(EVENTS_CAPACITY);
+        let idle_poll = Duration::from_millis(IDLE_POLL_MILLIS);
+        let mut last_idle_check = Instant::now();
+        let mut draining_since: Option<Instant> = None;
+
+        loop {
+            //El timeout solo sirve para revisar cada tanto las conexiones inactivas
+            if let Err(error) = self.poll.poll(&mut events, Some(idle_poll)) {
+                if error.kind() == ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(Box::new(error));
+            }
+            for event in events.iter() {
+                match event.token() {
+                    WAKER_TOKEN => (),
+                    Token(index) if index < self.listeners.len() => self.accept(index),
+                    token => {
+                        if event.is_readable() || event.is_read_closed() {
+                            self.read(token);
+                        }
+                        if event.is_writable() {
+                            self.write(token);
+                        }
+                    }
+                }
+            }
+            while let Ok(response) = self.receiver.try_recv() {
+                self.finish_request(response);
+            }
+            if last_idle_check.elapsed() >= idle_poll {
+                self.close_idle_connections();
+                last_idle_check = Instant::now();
+            }
+
+            if draining_since.is_none() && self.global_shutdown.is_set() {
+                info!("Waiting for in-flight requests to finish");
+                self.start_draining();
+                draining_since = Some(Instant::now());
+            }
+            if let Some(draining_since) = draining_since {
+                let shutdown_timeout = Duration::from_secs(self.config.shutdown_timeout_secs);
+                if self.connections.is_empty() || draining_since.elapsed() >= shutdown_timeout {
+                    return Ok(draining_since);
+                }
+            }
+        }
+    }
+}
+
+//Atiende las conexiones de todos los listeners (por ejemplo uno de IPv4 y otro de IPv6) con un loop de
+//eventos, que le pasa al pool los requests completos. Al activarse el shutdown global se deja de aceptar
+//conexiones y se espera a que terminen los requests en curso, devolviendo un error si no terminan a tiempo.
+pub fn general_communication(
+    listeners: Vec<TcpListener>,
+    mutex_of_torrents: ArcMutexOfTorrents,
+    global_shutdown: Arc<GlobalShutdown>,
+    config: Arc<TrackerConfig>,
+    stats: Arc<Stats>,
+    metrics: Arc<Metrics>,
+) -> ResultDyn<()> {
+    let mut pool = ThreadPool::new(config.pool_workers, config.pool_queue_size);
+    metrics.set_pool(pool.get_monitor());
+
+    let draining_since = {
+        let mut event_loop = EventLoop::new(
+            listeners,
+            &pool,
+            &mutex_of_torrents,
+            &global_shutdown,
+            &config,
+            &stats,
+            &metrics,
+        )?;
+        //El loop se despierta apenas se activa el shutdown, sin tener que revisarlo periodicamente
+        let waker = Arc::clone(&event_loop.waker);
+        let shutdown_copy = Arc::clone(&global_shutdown);
+        thread::spawn(move || {
+            shutdown_copy.wait();
+            let _ = waker.wake();
+        });
+        event_loop.run()?
+    };
+
+    let shutdown_timeout = Duration::from_secs(config.shutdown_timeout_secs);
+    pool.shutdown(shutdown_timeout.saturating_sub(draining_since.elapsed()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tracker::{
+        bencoding::decoder::from_torrent_to_dic,
+        data::{
+            ids::InfoHash,
+            peer_info::Event,
+            sharded_torrents::ShardedTorrents,
+            torrent_info::{tests::test_peer, TorrentInfo},
+        },
+    };
+
+    const FIRST_HASH: &str = "abcdefghijklmn123456";
+    const SECOND_HASH: &str = "zzzzzzzzzzzzzzzzzzzz";
+
+    //Dos torrents registrados, el primero con un seeder
+    fn get_torrents() -> ArcMutexOfTorrents {
+        let config = TrackerConfig::default();
+        let dic_torrents = Arc::new(ShardedTorrents::new(
+            TORRENT_SHARDS,
+            HashMap::new(),
+            Arc::new(Metrics::new()),
+        ));
+        for info_hash in [FIRST_HASH, SECOND_HASH] {
+            let info_hash = InfoHash::from_bytes(info_hash.as_bytes()).unwrap();
+            let mut shard = dic_torrents.write_shard(&info_hash).unwrap();
+            shard.insert(TorrentInfo::new(info_hash, &config));
+        }
+
+        //El seeder se une como leecher y luego avisa que completo la descarga
+        let info_hash = InfoHash::from_bytes(FIRST_HASH.as_bytes()).unwrap();
+        let mut shard = dic_torrents.write_shard(&info_hash).unwrap();
+        for (left, event) in [(10, Event::Started), (0, Event::Completed)] {
+            let peer_info = test_peer(FIRST_HASH, 1, Some(event), left, 0, 0, None);
+            shard
+                .get_mut(&info_hash)
+                .unwrap()
+                .add_peer(peer_info.get_peer_id(), peer_info);
+        }
+        drop(shard);
+        dic_torrents
+    }
+
+    //Devuelve el diccionario "files" de la respuesta al scrape con el query dado
+    fn scrape(query: &str) -> HashMap<Vec<u8>, ValuesBencoding> {
+        let head = format!("GET /scrape?{} HTTP/1.1", query);
+        let request = HttpRequest::parse(head.as_bytes()).unwrap();
+        let response = get_response_scrape(&request, &get_torrents()).unwrap();
+        match from_torrent_to_dic(&response).unwrap().remove(FILES_BYTES) {
+            Some(ValuesBencoding::Dic(files)) => files,
+            _ => panic!("Scrape response without files"),
+        }
+    }
+
+    fn get_stat(files: &HashMap<Vec<u8>, ValuesBencoding>, info_hash: &str, key: &[u8]) -> i64 {
+        match files.get(info_hash.as_bytes()) {
+            Some(ValuesBencoding::Dic(stats)) => match stats.get(key) {
+                Some(ValuesBencoding::Integer(value)) => *value,
+                _ => panic!("Scrape stats without {:?}", key),
+            },
+            _ => panic!("Scrape response without {}", info_hash),
+        }
+    }
+
+    #[test]
+    fn scrape_single_info_hash_ok() {
+        let files = scrape(&format!("info_hash={}", FIRST_HASH));
+        assert_eq!(1, files.len());
+        assert_eq!(1, get_stat(&files, FIRST_HASH, COMPLETE_BYTES));
+        assert_eq!(1, get_stat(&files, FIRST_HASH, DOWNLOADED_BYTES));
+        assert_eq!(0, get_stat(&files, FIRST_HASH, INCOMPLETE_BYTES));
+    }
+
+    #[test]
+    fn scrape_multiple_info_hashes_ok() {
+        let files = scrape(&format!(
+            "info_hash={}&info_hash={}",
+            FIRST_HASH, SECOND_HASH
+        ));
+        assert_eq!(2, files.len());
+        assert_eq!(1, get_stat(&files, FIRST_HASH, COMPLETE_BYTES));
+        assert_eq!(0, get_stat(&files, SECOND_HASH, COMPLETE_BYTES));
+        assert_eq!(0, get_stat(&files, SECOND_HASH, DOWNLOADED_BYTES));
+    }
+
+    #[test]
+    fn scrape_unknown_info_hash_is_ignored_ok() {
+        let files = scrape("info_hash=unknownunknownunknow");
+        assert!(files.is_empty());
+
+        //Sin info_hash se devuelven todos los torrents
+        assert_eq!(2, scrape("").len());
+    }
+
+    //Levanta el loop de eventos en un puerto libre y devuelve su direccion, el aviso de cierre y el hilo que
+    //devuelve si el cierre fue correcto
+    fn start_tracker(
+        config: TrackerConfig,
+    ) -> (SocketAddr, Arc<GlobalShutdown>, thread::JoinHandle<bool>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let global_shutdown = Arc::new(GlobalShutdown::new());
+        let shutdown_copy = Arc::clone(&global_shutdown);
+        let join_handle = thread::spawn(move || {
+            general_communication(
+                vec![listener],
+                get_torrents(),
+                shutdown_copy,
+                Arc::new(config),
+                Arc::new(Stats::new()),
+                Arc::new(Metrics::new()),
+            )
+            .is_ok()
+        });
+        (address, global_shutdown, join_handle)
+    }
+
+    fn connect(address: SocketAddr) -> std::net::TcpStream {
+        let stream = std::net::TcpStream::connect(address).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        stream
+    }
+
+    fn scrape_request(connection: &str) -> String {
+        format!(
+            "GET /scrape?info_hash={} HTTP/1.1\r\nConnection: {}\r\n\r\n",
+            FIRST_HASH, connection
+        )
+    }
+
+    #[test]
+    fn idle_connections_do_not_block_requests_ok() {
+        let config = TrackerConfig {
+            pool_workers: 2,
+            ..TrackerConfig::default()
         };
+        let (address, global_shutdown, join_handle) = start_tracker(config);
+
+        //Mas conexiones inactivas que workers, una de ellas con un request a medio mandar
+        let mut idle: Vec<_> = (0..4).map(|_| connect(address)).collect();
+        idle[0].write_all("GET /scrape?info_ha".as_bytes()).unwrap();
+
+        let mut stream = connect(address);
+        stream.write_all(scrape_request(CLOSE).as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with(OK_URL));
+
+        global_shutdown.set();
+        assert!(join_handle.join().unwrap());
+    }
+
+    #[test]
+    fn pipelined_requests_are_answered_in_order_ok() {
+        let (address, global_shutdown, join_handle) = start_tracker(TrackerConfig::default());
+
+        //Los tres requests llegan en el mismo buffer, y el ultimo pide cerrar la conexion
+        let mut stream = connect(address);
+        let requests = [
+            scrape_request(KEEP_ALIVE),
+            "GET /unknown HTTP/1.1\r\n\r\n".to_owned(),
+            scrape_request(CLOSE),
+        ]
+        .concat();
+        stream.write_all(requests.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        //Los bodies no terminan en salto de linea, por lo que cada status line empieza justo despues del
+        //body de la respuesta anterior
+        let status_lines: Vec<&str> = response
+            .match_indices("HTTP/1.1 ")
+            .map(|(index, _)| {
+                let line = &response[index..];
+                &line[..line.find("\r\n").unwrap()]
+            })
+            .collect();
+        assert_eq!(vec![OK_URL, ERR_URL, OK_URL], status_lines);
+        assert_eq!(2, response.matches("Connection: keep-alive").count());
+
+        global_shutdown.set();
+        assert!(join_handle.join().unwrap());
+    }
+
+    #[test]
+    fn connection_close_closes_after_response_ok() {
+        let (address, global_shutdown, join_handle) = start_tracker(TrackerConfig::default());
+
+        let mut stream = connect(address);
+        stream.write_all(scrape_request(CLOSE).as_bytes()).unwrap();
+        //Leer hasta el final solo termina si el tracker cierra la conexion luego de responder
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with(OK_URL));
+        assert_eq!(1, response.matches("Connection: close").count());
+
+        global_shutdown.set();
+        assert!(join_handle.join().unwrap());
+    }
+
+    #[test]
+    fn idle_keep_alive_connection_is_closed_ok() {
+        let config = TrackerConfig {
+            keep_alive_timeout_secs: 1,
+            ..TrackerConfig::default()
+        };
+        let (address, global_shutdown, join_handle) = start_tracker(config);
+
+        let mut stream = connect(address);
+        stream
+            .write_all(scrape_request(KEEP_ALIVE).as_bytes())
+            .unwrap();
+        let mut buffer = [0; MAX_REQUEST_SIZE];
+        let size = stream.read(&mut buffer).unwrap();
+        assert!(buffer[..size].starts_with(OK_URL.as_bytes()));
+
+        //Sin mandar otro request la conexion se cierra al pasar el timeout
+        let start = Instant::now();
+        assert_eq!(0, stream.read(&mut buffer).unwrap());
+        assert!(start.elapsed() >= Duration::from_millis(500));
+
+        global_shutdown.set();
+        assert!(join_handle.join().unwrap());
     }
 }
@@ -1 +1,3 @@
 pub mod handler;
+pub mod request;
+pub mod udp_handler;
@@ -0,0 +1,275 @@
+//! # Modulo de requests HTTP
+//! Contiene el parser de los requests HTTP/1.1 que recibe el tracker, a partir del cual se obtiene
+//! el metodo, el path, el query string y los headers del request
+
+use std::{collections::HashMap, error::Error, fmt};
+
+use crate::tracker::data::constants::*;
+
+///Posibles errores al leer un request
+#[derive(Debug, PartialEq, Eq)]
+pub enum HttpError {
+    ///El request no respeta el formato HTTP
+    Malformed,
+    ///El request supera el tamaño maximo permitido
+    TooLarge,
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\n    {:#?}\n", self)
+    }
+}
+
+impl Error for HttpError {}
+
+///Request HTTP ya parseado
+#[derive(Debug, PartialEq, Eq)]
+pub struct HttpRequest {
+    method: String,
+    path: String,
+    query: Vec<u8>,
+    version: String,
+    headers: HashMap<String, String>,
+}
+
+fn find_end_of_headers(buffer: &[u8]) -> Option<usize> {
+    buffer
+        .windows(END_OF_HEADERS.len())
+        .position(|window| window == END_OF_HEADERS)
+        .map(|pos| pos + END_OF_HEADERS.len())
+}
+
+impl HttpRequest {
+    ///Parsea la cabecera de un request (la request line y los headers, sin el "\r\n\r\n" final)
+    pub fn parse(head: &[u8]) -> Result<Self, HttpError> {
+        let head = std::str::from_utf8(head).map_err(|_| HttpError::Malformed)?;
+        let mut lines = head.split("\r\n");
+
+        let request_line = lines.next().ok_or(HttpError::Malformed)?;
+        let mut parts = request_line.split(' ');
+        let (method, target, version) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(method), Some(target), Some(version)) if parts.next().is_none() => {
+                (method, target, version)
+            }
+            _ => return Err(HttpError::Malformed),
+        };
+        if method.is_empty()
+            || !target.starts_with('/')
+            || !version.starts_with(HTTP_VERSION_PREFIX)
+        {
+            return Err(HttpError::Malformed);
+        }
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path, query),
+            None => (target, ""),
+        };
+
+        let mut headers = HashMap::new();
+        for line in lines {
+            let (name, value) = line.split_once(':').ok_or(HttpError::Malformed)?;
+            headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_owned());
+        }
+
+        Ok(HttpRequest {
+            method: method.to_owned(),
+            path: path.to_owned(),
+            query: query.as_bytes().to_vec(),
+            version: version.to_owned(),
+            headers,
+        })
+    }
+
+    ///Saca de `pending` el primer request si ya llego completo (la cabecera y el body), o devuelve None si
+    /// todavia faltan bytes. Los bytes de los requests siguientes (pipelining) quedan en `pending`.
+    pub fn take_from(pending: &mut Vec<u8>) -> Result<Option<Self>, HttpError> {
+        let end_of_headers = match find_end_of_headers(pending) {
+            Some(end) if end > MAX_REQUEST_SIZE => return Err(HttpError::TooLarge),
+            Some(end) => end,
+            None if pending.len() > MAX_REQUEST_SIZE => return Err(HttpError::TooLarge),
+            None => return Ok(None),
+        };
+
+        let request = Self::parse(&pending[..end_of_headers - END_OF_HEADERS.len()])?;
+
+        //El tracker no usa el body de los requests, pero hay que sacarlo de `pending`. Los bytes se sacan
+        //recien al tener el request completo, asi si falta parte del body puede retomarse al llegar.
+        let body_len = request.get_content_length()?;
+        if body_len > MAX_REQUEST_SIZE {
+            return Err(HttpError::TooLarge);
+        }
+        if pending.len() < end_of_headers + body_len {
+            return Ok(None);
+        }
+        pending.drain(..end_of_headers + body_len);
+        Ok(Some(request))
+    }
+
+    fn get_content_length(&self) -> Result<usize, HttpError> {
+        match self.get_header(CONTENT_LENGTH_HEADER) {
+            Some(value) => value.parse::<usize>().map_err(|_| HttpError::Malformed),
+            None => Ok(0),
+        }
+    }
+
+    pub fn get_method(&self) -> &str {
+        &self.method
+    }
+
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+
+    ///Devuelve el query string tal cual llego, es decir sin decodificar el urlencoding
+    pub fn get_query(&self) -> &[u8] {
+        &self.query
+    }
+
+    pub fn get_version(&self) -> &str {
+        &self.version
+    }
+
+    ///Indica si el cliente quiere mantener la conexion abierta para los requests siguientes. En HTTP/1.1
+    /// es lo que ocurre por defecto salvo que se mande "Connection: close", mientras que en HTTP/1.0 hay
+    /// que pedirlo con "Connection: keep-alive".
+    pub fn wants_keep_alive(&self) -> bool {
+        let connection = self
+            .get_header(CONNECTION_HEADER)
+            .map(|value| value.to_ascii_lowercase());
+        match connection.as_deref() {
+            Some(CLOSE) => false,
+            Some(KEEP_ALIVE) => true,
+            _ => self.version == HTTP_1_1,
+        }
+    }
+
+    ///Devuelve el valor del header pedido, sin importar mayusculas o minusculas en su nombre
+    pub fn get_header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_ascii_lowercase())
+            .map(|value| value.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_request_with_query_ok() {
+        let head =
+            "GET /announce?info_hash=abc&port=6881 HTTP/1.1\r\nHost: localhost\r\nUser-Agent: test"
+                .as_bytes();
+        let request = HttpRequest::parse(head).unwrap();
+
+        assert_eq!("GET", request.get_method());
+        assert_eq!("/announce", request.get_path());
+        assert_eq!("info_hash=abc&port=6881".as_bytes(), request.get_query());
+        assert_eq!("HTTP/1.1", request.get_version());
+        assert_eq!(Some("localhost"), request.get_header("host"));
+        assert_eq!(Some("test"), request.get_header("USER-AGENT"));
+    }
+
+    #[test]
+    fn parse_request_path_is_exact_ok() {
+        let request = HttpRequest::parse("GET /codeXYZ HTTP/1.1".as_bytes()).unwrap();
+        assert_eq!("/codeXYZ", request.get_path());
+        assert!(request.get_query().is_empty());
+    }
+
+    #[test]
+    fn parse_request_error_malformed() {
+        assert_eq!(
+            Err(HttpError::Malformed),
+            HttpRequest::parse("GET /announce".as_bytes())
+        );
+        assert_eq!(
+            Err(HttpError::Malformed),
+            HttpRequest::parse("GET announce HTTP/1.1".as_bytes())
+        );
+        assert_eq!(
+            Err(HttpError::Malformed),
+            HttpRequest::parse("GET / HTTP/1.1\r\nHost localhost".as_bytes())
+        );
+    }
+
+    #[test]
+    fn keep_alive_depends_on_version_and_header_ok() {
+        let request = HttpRequest::parse("GET / HTTP/1.1".as_bytes()).unwrap();
+        assert!(request.wants_keep_alive());
+
+        let request = HttpRequest::parse("GET / HTTP/1.1\r\nConnection: Close".as_bytes()).unwrap();
+        assert!(!request.wants_keep_alive());
+
+        let request = HttpRequest::parse("GET / HTTP/1.0".as_bytes()).unwrap();
+        assert!(!request.wants_keep_alive());
+
+        let request =
+            HttpRequest::parse("GET / HTTP/1.0\r\nConnection: keep-alive".as_bytes()).unwrap();
+        assert!(request.wants_keep_alive());
+    }
+
+    #[test]
+    fn take_request_longer_than_a_chunk_ok() {
+        let long_query = "a".repeat(READ_CHUNK_SIZE * 2);
+        let raw = format!(
+            "GET /announce?{} HTTP/1.1\r\nHost: localhost\r\n\r\n",
+            long_query
+        );
+        let mut pending = raw.as_bytes().to_vec();
+
+        let request = HttpRequest::take_from(&mut pending).unwrap().unwrap();
+        assert_eq!(long_query.as_bytes(), request.get_query());
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn take_request_keeps_following_bytes_ok() {
+        let raw = "GET /a HTTP/1.1\r\nContent-Length: 3\r\n\r\nabcGET /b HTTP/1.1\r\n\r\nGET /c";
+        let mut pending = raw.as_bytes().to_vec();
+
+        let request = HttpRequest::take_from(&mut pending).unwrap().unwrap();
+        assert_eq!("/a", request.get_path());
+        let request = HttpRequest::take_from(&mut pending).unwrap().unwrap();
+        assert_eq!("/b", request.get_path());
+        //El inicio del request siguiente queda esperando el resto
+        assert_eq!(Ok(None), HttpRequest::take_from(&mut pending));
+        assert_eq!("GET /c".as_bytes(), pending);
+    }
+
+    #[test]
+    fn take_request_resumes_when_more_bytes_arrive_ok() {
+        let parts: [&[u8]; 3] = [b"GET /a HTTP/1.1\r\nContent-Le", b"ngth: 2\r\n\r\na", b"b"];
+        let mut pending = vec![];
+
+        //Mientras falte parte de la cabecera o del body no se saca nada de `pending`
+        for part in &parts[..2] {
+            pending.extend_from_slice(part);
+            assert_eq!(Ok(None), HttpRequest::take_from(&mut pending));
+        }
+        pending.extend_from_slice(parts[2]);
+        let request = HttpRequest::take_from(&mut pending).unwrap().unwrap();
+        assert_eq!("/a", request.get_path());
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn take_request_error_too_large() {
+        //Sin el final de la cabecera alcanza con superar el tamaño maximo
+        let mut pending = format!("GET /{}", "a".repeat(MAX_REQUEST_SIZE)).into_bytes();
+        assert_eq!(
+            Err(HttpError::TooLarge),
+            HttpRequest::take_from(&mut pending)
+        );
+
+        let mut pending = format!(
+            "GET / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_REQUEST_SIZE + 1
+        )
+        .into_bytes();
+        assert_eq!(
+            Err(HttpError::TooLarge),
+            HttpRequest::take_from(&mut pending)
+        );
+    }
+}
@@ -0,0 +1,552 @@
+use std::{
+    io::ErrorKind,
+    net::{IpAddr, SocketAddr, UdpSocket},
+    sync::Arc,
+    time::Duration,
+};
+
+use log::{error, info};
+use sha1::{Digest, Sha1};
+
+use crate::{
+    tracker::{
+        config::TrackerConfig,
+        data::{
+            constants::*,
+            ids::{InfoHash, PeerId},
+            peer_info::{Event, PeerInfo, PeerInfoError},
+        },
+        metrics::Metrics,
+        registry::get_or_register_torrent,
+        shutdown::GlobalShutdown,
+        snapshot::get_unix_time,
+        stats::Stats,
+    },
+    ArcMutexOfTorrents, ResultDyn,
+};
+
+//Los connection ids no se guardan, sino que se derivan de la ip del cliente y del periodo en que se
+//entregaron usando un secreto que cambia en cada periodo, por lo que una lluvia de connects con direcciones
+//falsas no ocupa memoria. Un id se acepta en el periodo en que se entrego y en el siguiente, asi que dura
+//al menos `UDP_CONNECTION_ID_SECS` como pide el BEP 15.
+struct ConnectionIds {
+    period: u64,
+    secret: [u8; 16],
+    previous_secret: [u8; 16],
+}
+
+impl ConnectionIds {
+    fn new(now: u64) -> Self {
+        ConnectionIds {
+            period: now / UDP_CONNECTION_ID_SECS,
+            secret: rand::random(),
+            previous_secret: rand::random(),
+        }
+    }
+
+    //Cambia los secretos si empezo un nuevo periodo, conservando el anterior solo si es el inmediato
+    fn rotate(&mut self, now: u64) {
+        let period = now / UDP_CONNECTION_ID_SECS;
+        if period == self.period {
+            return;
+        }
+        self.previous_secret = match period == self.period + 1 {
+            true => self.secret,
+            false => rand::random(),
+        };
+        self.secret = rand::random();
+        self.period = period;
+    }
+
+    fn get_id(secret: &[u8], ip: IpAddr, period: u64) -> u64 {
+        let mut hasher = Sha1::new();
+        hasher.update(secret);
+        match ip {
+            IpAddr::V4(ip) => hasher.update(ip.octets()),
+            IpAddr::V6(ip) => hasher.update(ip.octets()),
+        }
+        hasher.update(period.to_be_bytes());
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&hasher.finalize()[..8]);
+        u64::from_be_bytes(bytes)
+    }
+
+    fn issue(&mut self, ip: IpAddr, now: u64) -> u64 {
+        self.rotate(now);
+        Self::get_id(&self.secret, ip, self.period)
+    }
+
+    //Un connection id es valido si se le entrego a la misma ip que lo usa y todavia no expiro
+    fn is_valid(&mut self, connection_id: u64, ip: IpAddr, now: u64) -> bool {
+        self.rotate(now);
+        connection_id == Self::get_id(&self.secret, ip, self.period)
+            || (self.period > 0
+                && connection_id == Self::get_id(&self.previous_secret, ip, self.period - 1))
+    }
+}
+
+fn read_u32(packet: &[u8], pos: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&packet[pos..pos + 4]);
+    u32::from_be_bytes(bytes)
+}
+
+fn read_u64(packet: &[u8], pos: usize) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&packet[pos..pos + 8]);
+    u64::from_be_bytes(bytes)
+}
+
+fn to_u32(value: i64) -> u32 {
+    value.try_into().unwrap_or(0)
+}
+
+fn get_error_response(transaction_id: u32, message: &str) -> Vec<u8> {
+    let mut response = UDP_ACTION_ERROR.to_be_bytes().to_vec();
+    response.extend_from_slice(&transaction_id.to_be_bytes());
+    response.extend_from_slice(message.as_bytes());
+    response
+}
+
+//El evento 0 (ninguno) y los desconocidos se toman como un announce periodico
+fn get_udp_event(event: u32) -> Option<Event> {
+    match event {
+        UDP_EVENT_COMPLETED => Some(Event::Completed),
+        UDP_EVENT_STARTED => Some(Event::Started),
+        UDP_EVENT_STOPPED => Some(Event::Stopped),
+        _ => None,
+    }
+}
+
+fn get_response_connect(
+    transaction_id: u32,
+    connection_ids: &mut ConnectionIds,
+    sock_addr: SocketAddr,
+    now: u64,
+) -> Vec<u8> {
+    let connection_id = connection_ids.issue(sock_addr.ip(), now);
+
+    let mut response = UDP_ACTION_CONNECT.to_be_bytes().to_vec();
+    response.extend_from_slice(&transaction_id.to_be_bytes());
+    response.extend_from_slice(&connection_id.to_be_bytes());
+    response
+}
+
+fn get_response_announce(
+    packet: &[u8],
+    transaction_id: u32,
+    dic_torrents: &ArcMutexOfTorrents,
+    sock_addr: SocketAddr,
+    config: &TrackerConfig,
+    stats: &Stats,
+    metrics: &Metrics,
+) -> ResultDyn<Vec<u8>> {
+    if packet.len() < UDP_ANNOUNCE_LEN {
+        return Ok(get_error_response(transaction_id, ERROR_UDP_MALFORMED));
+    }
+    //Como el largo del paquete ya se reviso, los dos identificadores tienen 20 bytes
+    let (info_hash, peer_id) = match (
+        InfoHash::from_bytes(&packet[16..36]),
+        PeerId::from_bytes(&packet[36..56]),
+    ) {
+        (Some(info_hash), Some(peer_id)) => (info_hash, peer_id),
+        _ => return Ok(get_error_response(transaction_id, ERROR_UDP_MALFORMED)),
+    };
+    let downloaded = read_u64(packet, 56);
+    let left = read_u64(packet, 64);
+    let uploaded = read_u64(packet, 72);
+    let event = get_udp_event(read_u32(packet, 80));
+    //Un num_want negativo (por defecto -1) indica que se use la cantidad por defecto
+    let numwant = u64::try_from(read_u32(packet, 92) as i32).ok();
+    let port = u16::from_be_bytes([packet[96], packet[97]]);
+
+    let info_of_announced_peer = match PeerInfo::new_from_udp(
+        info_hash, peer_id, port, downloaded, uploaded, left, event, numwant, sock_addr,
+    )
+    .and_then(|peer| peer.check_port_allowed(config))
+    {
+        Ok(peer_info) => peer_info,
+        Err(error) => {
+            metrics.add_announce_error(&error);
+            return Ok(get_error_response(transaction_id, ERROR_UDP_PEER));
+        }
+    };
+
+    let info_hash = info_of_announced_peer.get_info_hash();
+    let mut shard = dic_torrents.write_shard(&info_hash)?;
+    let torrent = match get_or_register_torrent(&mut shard, &info_of_announced_peer, config) {
+        Some(torrent) => torrent,
+        None => {
+            metrics.add_announce_error(&PeerInfoError::InfoHashInvalid);
+            return Ok(get_error_response(transaction_id, ERROR_UDP_INFO_HASH));
+        }
+    };
+    if let Err(error) = torrent.check_announce_interval(&info_of_announced_peer) {
+        metrics.add_announce_error(&error);
+        return Ok(get_error_response(transaction_id, ERROR_UDP_TOO_FAST));
+    }
+
+    let peer_id = info_of_announced_peer.get_peer_id();
+    let (complete, incomplete) = torrent.get_number_of_complete_and_incomplete_peers();
+    let mut response = UDP_ACTION_ANNOUNCE.to_be_bytes().to_vec();
+    response.extend_from_slice(&transaction_id.to_be_bytes());
+    response.extend_from_slice(&to_u32(torrent.get_interval()).to_be_bytes());
+    response.extend_from_slice(&to_u32(incomplete).to_be_bytes());
+    response.extend_from_slice(&to_u32(complete).to_be_bytes());
+    //Segun el BEP 15, a los announce recibidos por IPv6 se les responde con peers de 18 bytes
+    let (mut peers, mut peers6) = torrent.get_compact_peers(&info_of_announced_peer);
+    if sock_addr.is_ipv6() {
+        response.append(&mut peers6);
+    } else {
+        response.append(&mut peers);
+    }
+
+    let delta = torrent.add_peer(peer_id, info_of_announced_peer);
+    stats.add_announce(get_unix_time(), delta);
+    Ok(response)
+}
+
+fn get_response_scrape(
+    packet: &[u8],
+    transaction_id: u32,
+    dic_torrents: &ArcMutexOfTorrents,
+) -> ResultDyn<Vec<u8>> {
+    let mut response = UDP_ACTION_SCRAPE.to_be_bytes().to_vec();
+    response.extend_from_slice(&transaction_id.to_be_bytes());
+
+    //Los info_hash desconocidos se responden con todas sus estadisticas en 0 para mantener el orden
+    for info_hash in packet[UDP_SCRAPE_MIN_LEN..]
+        .chunks_exact(ID_LEN)
+        .filter_map(InfoHash::from_bytes)
+        .take(UDP_MAX_SCRAPE_HASHES)
+    {
+        let shard = dic_torrents.read_shard(&info_hash)?;
+        let (complete, completed, incomplete) = match shard.get(&info_hash) {
+            Some(torrent) => {
+                let (complete, incomplete) = torrent.get_number_of_complete_and_incomplete_peers();
+                (complete, torrent.get_completed(), incomplete)
+            }
+            None => (0, 0, 0),
+        };
+        response.extend_from_slice(&to_u32(complete).to_be_bytes());
+        response.extend_from_slice(&to_u32(completed).to_be_bytes());
+        response.extend_from_slice(&to_u32(incomplete).to_be_bytes());
+    }
+    Ok(response)
+}
+
+//Devuelve la respuesta al paquete recibido en el momento `now` (tiempo de unix), o None si el paquete
+//debe ignorarse
+#[allow(clippy::too_many_arguments)]
+fn get_response_for_packet(
+    packet: &[u8],
+    dic_torrents: &ArcMutexOfTorrents,
+    connection_ids: &mut ConnectionIds,
+    sock_addr: SocketAddr,
+    now: u64,
+    config: &TrackerConfig,
+    stats: &Stats,
+    metrics: &Metrics,
+) -> ResultDyn<Option<Vec<u8>>> {
+    if packet.len() < UDP_CONNECT_LEN {
+        return Ok(None);
+    }
+    let connection_id = read_u64(packet, 0);
+    let action = read_u32(packet, 8);
+    let transaction_id = read_u32(packet, 12);
+
+    if action == UDP_ACTION_CONNECT {
+        if connection_id != UDP_PROTOCOL_ID {
+            return Ok(None);
+        }
+        stats.add_connection(now);
+        return Ok(Some(get_response_connect(
+            transaction_id,
+            connection_ids,
+            sock_addr,
+            now,
+        )));
+    }
+
+    if !connection_ids.is_valid(connection_id, sock_addr.ip(), now) {
+        return Ok(Some(get_error_response(
+            transaction_id,
+            ERROR_UDP_CONNECTION_ID,
+        )));
+    }
+
+    let response = match action {
+        UDP_ACTION_ANNOUNCE => get_response_announce(
+            packet,
+            transaction_id,
+            dic_torrents,
+            sock_addr,
+            config,
+            stats,
+            metrics,
+        )?,
+        UDP_ACTION_SCRAPE => get_response_scrape(packet, transaction_id, dic_torrents)?,
+        _ => get_error_response(transaction_id, ERROR_UDP_ACTION),
+    };
+    Ok(Some(response))
+}
+
+pub fn general_communication_udp(
+    socket: UdpSocket,
+    mutex_of_torrents: ArcMutexOfTorrents,
+    global_shutdown: Arc<GlobalShutdown>,
+    config: Arc<TrackerConfig>,
+    stats: Arc<Stats>,
+    metrics: Arc<Metrics>,
+) {
+    let mut connection_ids = ConnectionIds::new(get_unix_time());
+    let mut buffer = [0; UDP_MAX_PACKET];
+
+    //El timeout permite revisar periodicamente si hay que cerrar el tracker
+    let _ = socket.set_read_timeout(Some(Duration::from_secs(1)));
+
+    loop {
+        if global_shutdown.is_set() {
+            break;
+        }
+        match socket.recv_from(&mut buffer) {
+            Ok((size, sock_addr)) => {
+                match get_response_for_packet(
+                    &buffer[..size],
+                    &mutex_of_torrents,
+                    &mut connection_ids,
+                    sock_addr,
+                    get_unix_time(),
+                    &config,
+                    &stats,
+                    &metrics,
+                ) {
+                    Ok(Some(response)) => {
+                        if let Err(error) = socket.send_to(&response, sock_addr) {
+                            error!("{}", error);
+                        }
+                    }
+                    Ok(None) => info!("Ignored UDP packet from {}", sock_addr),
+                    Err(error) => error!("{}", error),
+                }
+            }
+            Err(error) => {
+                if error.kind() != ErrorKind::WouldBlock && error.kind() != ErrorKind::TimedOut {
+                    error!("{}", error);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tracker::data::{sharded_torrents::ShardedTorrents, torrent_info::TorrentInfo};
+    use std::collections::HashMap;
+
+    const NOW: u64 = 1_000_000;
+    const INFO_HASH: &str = "abcdefghijklmn123456";
+    const TRANSACTION_ID: u32 = 1234;
+
+    struct Tracker {
+        dic_torrents: ArcMutexOfTorrents,
+        connection_ids: ConnectionIds,
+        config: TrackerConfig,
+        stats: Stats,
+        metrics: Metrics,
+    }
+
+    impl Tracker {
+        fn new() -> Self {
+            let config = TrackerConfig::default();
+            let dic_torrents = Arc::new(ShardedTorrents::new(
+                TORRENT_SHARDS,
+                HashMap::new(),
+                Arc::new(Metrics::new()),
+            ));
+            let info_hash = InfoHash::from_bytes(INFO_HASH.as_bytes()).unwrap();
+            dic_torrents
+                .write_shard(&info_hash)
+                .unwrap()
+                .insert(TorrentInfo::new(info_hash, &config));
+            Tracker {
+                dic_torrents,
+                connection_ids: ConnectionIds::new(NOW),
+                config,
+                stats: Stats::new(),
+                metrics: Metrics::new(),
+            }
+        }
+
+        fn send(&mut self, packet: &[u8], from: &str, now: u64) -> Option<Vec<u8>> {
+            get_response_for_packet(
+                packet,
+                &self.dic_torrents,
+                &mut self.connection_ids,
+                from.parse().unwrap(),
+                now,
+                &self.config,
+                &self.stats,
+                &self.metrics,
+            )
+            .unwrap()
+        }
+
+        fn connect(&mut self, from: &str, now: u64) -> u64 {
+            let response = self.send(&get_header(UDP_PROTOCOL_ID, UDP_ACTION_CONNECT), from, now);
+            read_u64(&response.unwrap(), 8)
+        }
+    }
+
+    fn get_header(connection_id: u64, action: u32) -> Vec<u8> {
+        let mut packet = connection_id.to_be_bytes().to_vec();
+        packet.extend_from_slice(&action.to_be_bytes());
+        packet.extend_from_slice(&TRANSACTION_ID.to_be_bytes());
+        packet
+    }
+
+    fn get_announce(connection_id: u64, left: u64) -> Vec<u8> {
+        let mut packet = get_header(connection_id, UDP_ACTION_ANNOUNCE);
+        packet.extend_from_slice(INFO_HASH.as_bytes());
+        packet.extend_from_slice("ABCDEFGHIJKLMNOPQRST".as_bytes());
+        packet.extend_from_slice(&0u64.to_be_bytes());
+        packet.extend_from_slice(&left.to_be_bytes());
+        packet.extend_from_slice(&0u64.to_be_bytes());
+        packet.extend_from_slice(&UDP_EVENT_STARTED.to_be_bytes());
+        //ip, key y num_want (-1)
+        packet.extend_from_slice(&[0; 8]);
+        packet.extend_from_slice(&(-1i32).to_be_bytes());
+        packet.extend_from_slice(&6881u16.to_be_bytes());
+        packet
+    }
+
+    fn assert_error(response: Option<Vec<u8>>, message: &str) {
+        let response = response.unwrap();
+        assert_eq!(UDP_ACTION_ERROR, read_u32(&response, 0));
+        assert_eq!(TRANSACTION_ID, read_u32(&response, 4));
+        assert_eq!(message.as_bytes(), &response[8..]);
+    }
+
+    #[test]
+    fn connect_ok() {
+        let mut tracker = Tracker::new();
+        let response = tracker
+            .send(
+                &get_header(UDP_PROTOCOL_ID, UDP_ACTION_CONNECT),
+                "127.0.0.1:6881",
+                NOW,
+            )
+            .unwrap();
+        assert_eq!(16, response.len());
+        assert_eq!(UDP_ACTION_CONNECT, read_u32(&response, 0));
+        assert_eq!(TRANSACTION_ID, read_u32(&response, 4));
+    }
+
+    #[test]
+    fn connect_with_wrong_protocol_id_is_ignored_ok() {
+        let mut tracker = Tracker::new();
+        let packet = get_header(UDP_PROTOCOL_ID + 1, UDP_ACTION_CONNECT);
+        assert_eq!(None, tracker.send(&packet, "127.0.0.1:6881", NOW));
+    }
+
+    #[test]
+    fn short_packet_is_ignored_ok() {
+        let mut tracker = Tracker::new();
+        assert_eq!(None, tracker.send(&[1, 2, 3], "127.0.0.1:6881", NOW));
+        assert_eq!(None, tracker.send(&[], "127.0.0.1:6881", NOW));
+    }
+
+    #[test]
+    fn garbage_packet_error() {
+        let mut tracker = Tracker::new();
+        let response = tracker.send(&get_header(42, 7), "127.0.0.1:6881", NOW);
+        assert_error(response, ERROR_UDP_CONNECTION_ID);
+    }
+
+    #[test]
+    fn announce_ok() {
+        let mut tracker = Tracker::new();
+        let connection_id = tracker.connect("127.0.0.1:6881", NOW);
+        let response = tracker
+            .send(&get_announce(connection_id, 10), "127.0.0.1:6881", NOW)
+            .unwrap();
+
+        assert_eq!(UDP_ACTION_ANNOUNCE, read_u32(&response, 0));
+        assert_eq!(TRANSACTION_ID, read_u32(&response, 4));
+        assert_eq!(tracker.config.interval as u32, read_u32(&response, 8));
+        //Todavia no se contaba al propio peer, y no se le devuelve a si mismo
+        assert_eq!(0, read_u32(&response, 12));
+        assert_eq!(0, read_u32(&response, 16));
+        assert_eq!(20, response.len());
+
+        //Un segundo peer recibe al primero en formato compacto
+        let connection_id = tracker.connect("127.0.0.2:6881", NOW);
+        let mut packet = get_announce(connection_id, 10);
+        packet[36] = b'Z';
+        let response = tracker.send(&packet, "127.0.0.2:6881", NOW).unwrap();
+        assert_eq!(1, read_u32(&response, 12));
+        assert_eq!(vec![127, 0, 0, 1, 26, 225], response[20..].to_vec());
+    }
+
+    #[test]
+    fn announce_too_short_error() {
+        let mut tracker = Tracker::new();
+        let connection_id = tracker.connect("127.0.0.1:6881", NOW);
+        let packet = get_announce(connection_id, 10)[..UDP_ANNOUNCE_LEN - 1].to_vec();
+        let response = tracker.send(&packet, "127.0.0.1:6881", NOW);
+        assert_error(response, ERROR_UDP_MALFORMED);
+    }
+
+    #[test]
+    fn scrape_ok() {
+        let mut tracker = Tracker::new();
+        let connection_id = tracker.connect("127.0.0.1:6881", NOW);
+        tracker.send(&get_announce(connection_id, 0), "127.0.0.1:6881", NOW);
+
+        let mut packet = get_header(connection_id, UDP_ACTION_SCRAPE);
+        packet.extend_from_slice(INFO_HASH.as_bytes());
+        packet.extend_from_slice("zzzzzzzzzzzzzzzzzzzz".as_bytes());
+        let response = tracker.send(&packet, "127.0.0.1:6881", NOW).unwrap();
+
+        assert_eq!(UDP_ACTION_SCRAPE, read_u32(&response, 0));
+        assert_eq!(8 + 2 * 12, response.len());
+        //El seeder del torrent conocido, y todo en 0 para el desconocido
+        assert_eq!(1, read_u32(&response, 8));
+        assert_eq!(vec![0; 12], response[20..].to_vec());
+    }
+
+    #[test]
+    fn unknown_action_error() {
+        let mut tracker = Tracker::new();
+        let connection_id = tracker.connect("127.0.0.1:6881", NOW);
+        let response = tracker.send(&get_header(connection_id, 9), "127.0.0.1:6881", NOW);
+        assert_error(response, ERROR_UDP_ACTION);
+    }
+
+    #[test]
+    fn connection_id_lasts_at_least_its_duration_ok() {
+        let mut tracker = Tracker::new();
+        let connection_id = tracker.connect("127.0.0.1:6881", NOW);
+        let later = NOW + UDP_CONNECTION_ID_SECS;
+        let response = tracker.send(&get_announce(connection_id, 10), "127.0.0.1:6881", later);
+        assert_eq!(UDP_ACTION_ANNOUNCE, read_u32(&response.unwrap(), 0));
+    }
+
+    #[test]
+    fn expired_connection_id_error() {
+        let mut tracker = Tracker::new();
+        let connection_id = tracker.connect("127.0.0.1:6881", NOW);
+        let expired = NOW + 2 * UDP_CONNECTION_ID_SECS;
+        let response = tracker.send(&get_announce(connection_id, 10), "127.0.0.1:6881", expired);
+        assert_error(response, ERROR_UDP_CONNECTION_ID);
+    }
+
+    #[test]
+    fn connection_id_of_another_ip_error() {
+        let mut tracker = Tracker::new();
+        let connection_id = tracker.connect("127.0.0.1:6881", NOW);
+        let response = tracker.send(&get_announce(connection_id, 10), "127.0.0.2:6881", NOW);
+        assert_error(response, ERROR_UDP_CONNECTION_ID);
+    }
+}
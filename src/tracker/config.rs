@@ -0,0 +1,502 @@
+//! # Modulo de configuracion
+//! Contiene los parametros configurables del tracker junto con sus valores por defecto.
+//!
+//! Cada parametro se puede definir, de menor a mayor prioridad:
+//! - En un archivo de configuracion con una linea `clave=valor` por parametro (por defecto `config.txt`,
+//!   otro archivo se indica con `--config <ruta>` o con la variable de entorno `TRACKER_CONFIG`)
+//! - Con una variable de entorno `TRACKER_<CLAVE>`, por ejemplo `TRACKER_INTERVAL=900`
+//! - Con un flag `--<clave> <valor>` o `--<clave>=<valor>`, por ejemplo `--open_tracker true`
+
+use std::{error::Error, fmt, fs, net::SocketAddr, ops::RangeInclusive, path::Path};
+
+use super::data::constants::*;
+
+///Posibles errores al cargar la configuracion
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    ///No se pudo leer el archivo de configuracion indicado
+    ReadFile(String),
+    ///Una linea del archivo de configuracion no tiene el formato `clave=valor`
+    MalformedLine(usize, String),
+    ///Un flag de la linea de comandos no tiene valor
+    MissingValue(String),
+    ///La clave no corresponde a ningun parametro
+    UnknownKey(String),
+    ///El valor no es valido para el parametro (clave, valor)
+    InvalidValue(String, String),
+    ///La combinacion de parametros no es valida
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\n    {:#?}\n", self)
+    }
+}
+
+impl Error for ConfigError {}
+
+///Que hacer con una conexion nueva cuando la cola del pool de threads esta llena
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectionPolicy {
+    ///Se responde "503 Service Unavailable" y se cierra la conexion
+    ServiceUnavailable,
+    ///Se cierra la conexion sin responder
+    Drop,
+}
+
+///Como se tratan los puertos privilegiados (menores a 1024) en los announces
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrivilegedPortPolicy {
+    ///Se aceptan como cualquier otro puerto
+    Allow,
+    ///Se rechazan aunque esten en la lista de puertos permitidos
+    Deny,
+}
+
+///Lista de puertos, que se escribe como puertos o rangos separados por comas (por ejemplo "6881-6889,51413")
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PortList {
+    ranges: Vec<RangeInclusive<u16>>,
+}
+
+impl PortList {
+    ///Parsea la lista de puertos, devolviendo None si algun puerto o rango no es valido
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut ranges = vec![];
+        for item in value
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+        {
+            let (first, last) = item.split_once('-').unwrap_or((item, item));
+            let first = first.trim().parse::<u16>().ok()?;
+            let last = last.trim().parse::<u16>().ok()?;
+            if first == 0 || first > last {
+                return None;
+            }
+            ranges.push(first..=last);
+        }
+        Some(PortList { ranges })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    pub fn contains(&self, port: u16) -> bool {
+        self.ranges.iter().any(|range| range.contains(&port))
+    }
+}
+
+///Parametros con los que funciona el tracker
+#[derive(Debug, Clone)]
+pub struct TrackerConfig {
+    ///Direcciones en las que escucha el tracker, tanto por HTTP como por UDP
+    pub listen_addresses: Vec<SocketAddr>,
+    ///Segundos que se le indica a los peers que esperen entre announces
+    pub interval: i64,
+    ///Segundos minimos entre announces de un mismo peer, si anuncia antes se lo rechaza
+    pub min_interval: i64,
+    ///Cantidad de intervalos sin anunciar tras los cuales un peer se elimina del swarm
+    pub peer_expiry_factor: u64,
+    ///Cantidad maxima de peers que se devuelven en un announce, sin importar el numwant pedido
+    pub max_numwant: u64,
+    ///Puertos con los que los peers pueden anunciarse (vacia para aceptar cualquiera)
+    pub allowed_ports: PortList,
+    ///Puertos con los que los peers no pueden anunciarse
+    pub denied_ports: PortList,
+    ///Si se aceptan announces con puertos privilegiados
+    pub privileged_ports: PrivilegedPortPolicy,
+    ///Directorio con los archivos .torrent permitidos en el tracker
+    pub torrents_dir: String,
+    ///Archivo con los info hash permitidos en hexadecimal, uno por linea
+    pub whitelist_path: String,
+    ///Archivo en el que se guarda el estado de los swarms (vacio para no guardarlo)
+    pub snapshot_path: String,
+    ///Cada cuantos segundos se guarda el estado de los swarms (0 para guardarlo solo al cerrar el tracker)
+    pub snapshot_interval_secs: u64,
+    ///Directorio desde el que se sirven las paginas (index.html, stats.html, etc)
+    pub static_dir: String,
+    ///Cada cuantos segundos se vuelve a leer el registro de torrents (0 para no hacerlo)
+    pub registry_rescan_secs: u64,
+    ///Si es true cualquier info hash desconocido se registra en su primer announce con evento started
+    pub open_tracker: bool,
+    ///Cantidad maxima de torrents que puede haber en el tracker en modo abierto
+    pub max_torrents: usize,
+    ///Segundos que se espera un nuevo request en una conexion persistente antes de cerrarla
+    pub keep_alive_timeout_secs: u64,
+    ///Cantidad maxima de requests que se atienden por una misma conexion
+    pub max_requests_per_connection: usize,
+    ///Cantidad de threads que atienden las conexiones HTTP
+    pub pool_workers: usize,
+    ///Cantidad maxima de conexiones esperando un thread libre
+    pub pool_queue_size: usize,
+    ///Que hacer con las conexiones que llegan con la cola llena
+    pub pool_rejection_policy: RejectionPolicy,
+    ///Segundos que se espera a que terminen los requests en curso al cerrar el tracker
+    pub shutdown_timeout_secs: u64,
+}
+
+impl Default for TrackerConfig {
+    fn default() -> Self {
+        TrackerConfig {
+            listen_addresses: [TRACKER_ADDR_V4, TRACKER_ADDR_V6]
+                .iter()
+                .filter_map(|address| address.parse().ok())
+                .collect(),
+            interval: DEFAULT_INTERVAL,
+            min_interval: DEFAULT_MIN_INTERVAL,
+            peer_expiry_factor: DEFAULT_PEER_EXPIRY_FACTOR,
+            max_numwant: DEFAULT_MAX_NUMWANT,
+            allowed_ports: PortList::default(),
+            denied_ports: PortList::default(),
+            privileged_ports: PrivilegedPortPolicy::Allow,
+            torrents_dir: TORRENTS_DIR.to_owned(),
+            whitelist_path: WHITELIST_PATH.to_owned(),
+            snapshot_path: SNAPSHOT_PATH.to_owned(),
+            snapshot_interval_secs: DEFAULT_SNAPSHOT_INTERVAL_SECS,
+            static_dir: STATIC_DIR.to_owned(),
+            registry_rescan_secs: DEFAULT_REGISTRY_RESCAN_SECS,
+            open_tracker: false,
+            max_torrents: DEFAULT_MAX_TORRENTS,
+            keep_alive_timeout_secs: DEFAULT_KEEP_ALIVE_TIMEOUT_SECS,
+            max_requests_per_connection: DEFAULT_MAX_REQUESTS_PER_CONNECTION,
+            pool_workers: DEFAULT_POOL_WORKERS,
+            pool_queue_size: DEFAULT_POOL_QUEUE_SIZE,
+            pool_rejection_policy: RejectionPolicy::ServiceUnavailable,
+            shutdown_timeout_secs: DEFAULT_SHUTDOWN_TIMEOUT_SECS,
+        }
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, ConfigError> {
+    value
+        .parse::<T>()
+        .map_err(|_| ConfigError::InvalidValue(key.to_owned(), value.to_owned()))
+}
+
+fn parse_addresses(key: &str, value: &str) -> Result<Vec<SocketAddr>, ConfigError> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|address| !address.is_empty())
+        .map(|address| parse_value(key, address))
+        .collect()
+}
+
+fn parse_port_list(key: &str, value: &str) -> Result<PortList, ConfigError> {
+    PortList::parse(value)
+        .ok_or_else(|| ConfigError::InvalidValue(key.to_owned(), value.to_owned()))
+}
+
+fn parse_privileged_port_policy(
+    key: &str,
+    value: &str,
+) -> Result<PrivilegedPortPolicy, ConfigError> {
+    match value {
+        "allow" => Ok(PrivilegedPortPolicy::Allow),
+        "deny" => Ok(PrivilegedPortPolicy::Deny),
+        _ => Err(ConfigError::InvalidValue(key.to_owned(), value.to_owned())),
+    }
+}
+
+fn parse_rejection_policy(key: &str, value: &str) -> Result<RejectionPolicy, ConfigError> {
+    match value {
+        "503" => Ok(RejectionPolicy::ServiceUnavailable),
+        "drop" => Ok(RejectionPolicy::Drop),
+        _ => Err(ConfigError::InvalidValue(key.to_owned(), value.to_owned())),
+    }
+}
+
+//Separa los flags de la linea de comandos en pares (clave, valor)
+fn parse_args(args: &[String]) -> Result<Vec<(String, String)>, ConfigError> {
+    let mut pairs = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let flag = arg
+            .strip_prefix(FLAG_PREFIX)
+            .ok_or_else(|| ConfigError::UnknownKey(arg.to_owned()))?;
+        let (key, value) = match flag.split_once('=') {
+            Some((key, value)) => (key.to_owned(), value.to_owned()),
+            None => match args.next() {
+                Some(value) => (flag.to_owned(), value.to_owned()),
+                None => return Err(ConfigError::MissingValue(arg.to_owned())),
+            },
+        };
+        pairs.push((key, value));
+    }
+    Ok(pairs)
+}
+
+//Lee los pares (clave, valor) del archivo de configuracion, ignorando lineas vacias y comentarios
+fn parse_file(content: &str) -> Result<Vec<(String, String)>, ConfigError> {
+    let mut pairs = vec![];
+    for (number, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(COMMENT_PREFIX) {
+            continue;
+        }
+        match line.split_once('=') {
+            Some((key, value)) => pairs.push((key.trim().to_owned(), value.trim().to_owned())),
+            None => return Err(ConfigError::MalformedLine(number + 1, line.to_owned())),
+        }
+    }
+    Ok(pairs)
+}
+
+impl TrackerConfig {
+    ///Carga la configuracion combinando los valores por defecto, el archivo de configuracion, las variables
+    /// de entorno y los flags de la linea de comandos (sin el nombre del programa), y luego la valida.
+    pub fn load(
+        args: impl Iterator<Item = String>,
+        vars: impl Iterator<Item = (String, String)>,
+    ) -> Result<Self, ConfigError> {
+        Self::load_from(args, vars, Path::new(CONFIG_PATH))
+    }
+
+    ///Igual que `load`, pero si no se indica el archivo de configuracion se usa `default_path` (si existe)
+    pub fn load_from(
+        args: impl Iterator<Item = String>,
+        vars: impl Iterator<Item = (String, String)>,
+        default_path: &Path,
+    ) -> Result<Self, ConfigError> {
+        let args: Vec<String> = args.collect();
+        let vars: Vec<(String, String)> = vars
+            .filter_map(|(name, value)| {
+                let key = name.strip_prefix(ENV_PREFIX)?.to_ascii_lowercase();
+                Some((key, value))
+            })
+            .collect();
+        let flags = parse_args(&args)?;
+
+        //El archivo de configuracion es el unico parametro que no puede estar dentro del mismo archivo
+        let config_path = flags
+            .iter()
+            .rev()
+            .chain(vars.iter())
+            .find(|(key, _)| key == CONFIG_KEY)
+            .map(|(_, path)| path.to_owned());
+
+        let mut config = TrackerConfig::default();
+        let file_pairs = match config_path {
+            Some(path) => {
+                let content = fs::read_to_string(&path)
+                    .map_err(|err| ConfigError::ReadFile(format!("{}: {}", path, err)))?;
+                parse_file(&content)?
+            }
+            None if default_path.is_file() => {
+                let content = fs::read_to_string(default_path).map_err(|err| {
+                    ConfigError::ReadFile(format!("{}: {}", default_path.display(), err))
+                })?;
+                parse_file(&content)?
+            }
+            None => vec![],
+        };
+
+        for (key, value) in file_pairs.iter().chain(vars.iter()).chain(flags.iter()) {
+            if key != CONFIG_KEY {
+                config.set(key, value)?;
+            }
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    ///Cambia el valor del parametro con la clave dada
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        match key {
+            "listen_addresses" => self.listen_addresses = parse_addresses(key, value)?,
+            "interval" => self.interval = parse_value(key, value)?,
+            "min_interval" => self.min_interval = parse_value(key, value)?,
+            "peer_expiry_factor" => self.peer_expiry_factor = parse_value(key, value)?,
+            "max_numwant" => self.max_numwant = parse_value(key, value)?,
+            "allowed_ports" => self.allowed_ports = parse_port_list(key, value)?,
+            "denied_ports" => self.denied_ports = parse_port_list(key, value)?,
+            "privileged_ports" => self.privileged_ports = parse_privileged_port_policy(key, value)?,
+            "torrents_dir" => self.torrents_dir = value.to_owned(),
+            "whitelist_path" => self.whitelist_path = value.to_owned(),
+            "snapshot_path" => self.snapshot_path = value.to_owned(),
+            "snapshot_interval_secs" => self.snapshot_interval_secs = parse_value(key, value)?,
+            "static_dir" => self.static_dir = value.to_owned(),
+            "registry_rescan_secs" => self.registry_rescan_secs = parse_value(key, value)?,
+            "open_tracker" => self.open_tracker = parse_value(key, value)?,
+            "max_torrents" => self.max_torrents = parse_value(key, value)?,
+            "keep_alive_timeout_secs" => self.keep_alive_timeout_secs = parse_value(key, value)?,
+            "max_requests_per_connection" => {
+                self.max_requests_per_connection = parse_value(key, value)?
+            }
+            "pool_workers" => self.pool_workers = parse_value(key, value)?,
+            "pool_queue_size" => self.pool_queue_size = parse_value(key, value)?,
+            "pool_rejection_policy" => {
+                self.pool_rejection_policy = parse_rejection_policy(key, value)?
+            }
+            "shutdown_timeout_secs" => self.shutdown_timeout_secs = parse_value(key, value)?,
+            _ => return Err(ConfigError::UnknownKey(key.to_owned())),
+        }
+        Ok(())
+    }
+
+    ///Revisa que los parametros tengan sentido en conjunto
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let error = if self.listen_addresses.is_empty() {
+            "listen_addresses can not be empty"
+        } else if self.interval <= 0 {
+            "interval must be positive"
+        } else if self.min_interval < 0 || self.min_interval > self.interval {
+            "min_interval must be between 0 and interval"
+        } else if self.peer_expiry_factor == 0 {
+            "peer_expiry_factor must be positive"
+        } else if self.keep_alive_timeout_secs == 0 {
+            "keep_alive_timeout_secs must be positive"
+        } else if self.max_requests_per_connection == 0 {
+            "max_requests_per_connection must be positive"
+        } else if self.pool_workers == 0 {
+            "pool_workers must be positive"
+        } else if self.pool_queue_size == 0 {
+            "pool_queue_size must be positive"
+        } else {
+            return Ok(());
+        };
+        Err(ConfigError::Invalid(error.to_owned()))
+    }
+
+    ///Indica si un peer puede anunciarse con el puerto dado. Se acepta cualquier puerto valido salvo que
+    /// este denegado, sea privilegiado y no se permitan, o no este en la lista de permitidos (si la hay).
+    pub fn is_port_allowed(&self, port: u16) -> bool {
+        if port == 0 || self.denied_ports.contains(port) {
+            return false;
+        }
+        if port < PRIVILEGED_PORT_LIMIT && self.privileged_ports == PrivilegedPortPolicy::Deny {
+            return false;
+        }
+        self.allowed_ports.is_empty() || self.allowed_ports.contains(port)
+    }
+
+    ///Devuelve la cantidad de segundos sin anunciar tras los cuales un peer se considera muerto
+    pub fn get_peer_expiry_secs(&self) -> u64 {
+        self.interval.max(0) as u64 * self.peer_expiry_factor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    //Carga la configuracion sin leer el config.txt del directorio en el que se corren los tests
+    fn load(
+        args: impl Iterator<Item = String>,
+        vars: impl Iterator<Item = (String, String)>,
+    ) -> Result<TrackerConfig, ConfigError> {
+        TrackerConfig::load_from(args, vars, Path::new(""))
+    }
+
+    fn to_args(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<String>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn flags_override_env_vars_ok() {
+        let vars = vec![
+            ("TRACKER_INTERVAL".to_owned(), "600".to_owned()),
+            ("TRACKER_MIN_INTERVAL".to_owned(), "100".to_owned()),
+            ("PATH".to_owned(), "/bin".to_owned()),
+        ];
+        let args = to_args(&["--interval", "1200", "--open_tracker=true"]);
+        let config = load(args, vars.into_iter()).unwrap();
+
+        assert_eq!(1200, config.interval);
+        assert_eq!(100, config.min_interval);
+        assert!(config.open_tracker);
+    }
+
+    #[test]
+    fn default_config_file_is_overridden_ok() {
+        let path = env::temp_dir().join(format!("config_{}.txt", std::process::id()));
+        fs::write(&path, "interval = 60\nmin_interval = 30\n").unwrap();
+        let vars = vec![("TRACKER_MIN_INTERVAL".to_owned(), "20".to_owned())];
+        let config = TrackerConfig::load_from(to_args(&[]), vars.into_iter(), &path);
+        let _ = fs::remove_file(&path);
+
+        let config = config.unwrap();
+        assert_eq!(60, config.interval);
+        assert_eq!(20, config.min_interval);
+    }
+
+    #[test]
+    fn parse_file_ignores_comments_ok() {
+        let content = "# comentario\n\ninterval = 60\nlisten_addresses=0.0.0.0:6969, [::]:6969\n";
+        let mut config = TrackerConfig::default();
+        for (key, value) in parse_file(content).unwrap() {
+            config.set(&key, &value).unwrap();
+        }
+
+        assert_eq!(60, config.interval);
+        assert_eq!(2, config.listen_addresses.len());
+        assert_eq!(
+            Err(ConfigError::MalformedLine(1, "interval".to_owned())),
+            parse_file("interval")
+        );
+    }
+
+    #[test]
+    fn load_errors_are_reported() {
+        let vars = || Vec::<(String, String)>::new().into_iter();
+        assert_eq!(
+            Err(ConfigError::UnknownKey("intervl".to_owned())),
+            load(to_args(&["--intervl", "5"]), vars()).map(|_| ())
+        );
+        assert_eq!(
+            Err(ConfigError::InvalidValue(
+                "pool_workers".to_owned(),
+                "four".to_owned()
+            )),
+            load(to_args(&["--pool_workers", "four"]), vars()).map(|_| ())
+        );
+        assert_eq!(
+            Err(ConfigError::MissingValue("--interval".to_owned())),
+            load(to_args(&["--interval"]), vars()).map(|_| ())
+        );
+        assert!(matches!(
+            load(to_args(&["--min_interval", "4000"]), vars()),
+            Err(ConfigError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn any_port_is_allowed_by_default_ok() {
+        let config = TrackerConfig::default();
+        assert!(config.is_port_allowed(80));
+        assert!(config.is_port_allowed(51413));
+        assert!(config.is_port_allowed(65535));
+        assert!(!config.is_port_allowed(0));
+    }
+
+    #[test]
+    fn port_lists_and_privileged_policy_ok() {
+        let mut config = TrackerConfig::default();
+        config.set("allowed_ports", "1-2000,6881-6889").unwrap();
+        config.set("denied_ports", "1500").unwrap();
+        config.set("privileged_ports", "deny").unwrap();
+
+        assert!(!config.is_port_allowed(80));
+        assert!(config.is_port_allowed(1024));
+        assert!(!config.is_port_allowed(1500));
+        assert!(config.is_port_allowed(6881));
+        assert!(!config.is_port_allowed(51413));
+    }
+
+    #[test]
+    fn port_list_ok() {
+        let ports = PortList::parse("6881-6889, 51413").unwrap();
+        assert!(ports.contains(6885));
+        assert!(ports.contains(51413));
+        assert!(!ports.contains(6890));
+        assert_eq!(None, PortList::parse("6889-6881"));
+        assert_eq!(None, PortList::parse("0"));
+    }
+}
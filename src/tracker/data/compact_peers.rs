@@ -0,0 +1,347 @@
+//! # Peers en formato compacto
+//! Mantiene los peers de un torrent ya codificados en el formato compacto de las respuestas (6 bytes por
+//! peer para IPv4 y 18 para IPv6, BEP 23 y BEP 7), separados en seeders y leechers. Los buffers se
+//! actualizan con cada announce, por lo que responder solo requiere copiar los peers elegidos.
+
+use std::collections::HashMap;
+
+use rand::seq::index;
+
+use super::{ids::PeerId, peer_info::PeerInfo};
+
+const V4_ENTRY_LEN: usize = 6;
+const V6_ENTRY_LEN: usize = 18;
+
+//Peers codificados uno detras del otro, junto con el id de cada uno en el mismo orden
+struct PeerBuffer {
+    entry_len: usize,
+    bytes: Vec<u8>,
+    ids: Vec<PeerId>,
+}
+
+impl PeerBuffer {
+    fn new(entry_len: usize) -> Self {
+        PeerBuffer {
+            entry_len,
+            bytes: vec![],
+            ids: vec![],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    //Agrega el peer al final y devuelve su posicion
+    fn push(&mut self, peer_id: PeerId, ip: &[u8], port: u16) -> usize {
+        self.bytes.extend_from_slice(ip);
+        self.bytes.extend_from_slice(&port.to_be_bytes());
+        self.ids.push(peer_id);
+        self.ids.len() - 1
+    }
+
+    //Saca al peer de la posicion dada poniendo en su lugar al ultimo, cuyo id se devuelve para que se
+    //actualice su posicion
+    fn swap_remove(&mut self, position: usize) -> Option<PeerId> {
+        let last = self.ids.len() - 1;
+        if position != last {
+            let (start, last_start) = (position * self.entry_len, last * self.entry_len);
+            self.bytes
+                .copy_within(last_start..last_start + self.entry_len, start);
+        }
+        self.bytes.truncate(last * self.entry_len);
+        self.ids.swap_remove(position);
+        self.ids.get(position).copied()
+    }
+
+    //Posiciones de hasta `amount` peers elegidos al azar, sin repetir y sin incluir al de la posicion
+    //`excluded`
+    fn sample_positions(
+        &self,
+        amount: usize,
+        excluded: Option<usize>,
+    ) -> impl Iterator<Item = usize> {
+        let available = self.len() - excluded.is_some() as usize;
+        index::sample(&mut rand::thread_rng(), available, amount.min(available))
+            .into_iter()
+            //Las posiciones se eligen entre las disponibles, salteando la excluida
+            .map(move |position| match excluded {
+                Some(excluded) if position >= excluded => position + 1,
+                _ => position,
+            })
+    }
+
+    //Agrega hasta `amount` peers elegidos al azar, sin repetir y sin incluir al de la posicion `excluded`
+    fn extend_random(&self, result: &mut Vec<u8>, amount: usize, excluded: Option<usize>) {
+        let available = self.len() - excluded.is_some() as usize;
+        //Si se piden todos se copia el buffer entero de una vez
+        if amount >= available {
+            match excluded {
+                Some(excluded) => {
+                    result.extend_from_slice(&self.bytes[..excluded * self.entry_len]);
+                    result.extend_from_slice(&self.bytes[(excluded + 1) * self.entry_len..]);
+                }
+                None => result.extend_from_slice(&self.bytes),
+            }
+            return;
+        }
+        for position in self.sample_positions(amount, excluded) {
+            let start = position * self.entry_len;
+            result.extend_from_slice(&self.bytes[start..start + self.entry_len]);
+        }
+    }
+
+    //Igual que `extend_random` pero agregando los ids de los peers elegidos
+    fn extend_random_ids(&self, result: &mut Vec<PeerId>, amount: usize, excluded: Option<usize>) {
+        result.extend(
+            self.sample_positions(amount, excluded)
+                .map(|position| self.ids[position]),
+        );
+    }
+}
+
+//Donde esta cada peer dentro de los buffers
+struct Slot {
+    complete: bool,
+    v4: Option<usize>,
+    v6: Option<usize>,
+}
+
+pub struct CompactPeers {
+    seeders_v4: PeerBuffer,
+    leechers_v4: PeerBuffer,
+    seeders_v6: PeerBuffer,
+    leechers_v6: PeerBuffer,
+    slots: HashMap<PeerId, Slot>,
+    complete: usize,
+}
+
+impl Default for CompactPeers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CompactPeers {
+    pub fn new() -> Self {
+        CompactPeers {
+            seeders_v4: PeerBuffer::new(V4_ENTRY_LEN),
+            leechers_v4: PeerBuffer::new(V4_ENTRY_LEN),
+            seeders_v6: PeerBuffer::new(V6_ENTRY_LEN),
+            leechers_v6: PeerBuffer::new(V6_ENTRY_LEN),
+            slots: HashMap::new(),
+            complete: 0,
+        }
+    }
+
+    fn get_buffers(&self, complete: bool) -> (&PeerBuffer, &PeerBuffer) {
+        match complete {
+            true => (&self.seeders_v4, &self.seeders_v6),
+            false => (&self.leechers_v4, &self.leechers_v6),
+        }
+    }
+
+    fn get_buffers_mut(&mut self, complete: bool) -> (&mut PeerBuffer, &mut PeerBuffer) {
+        match complete {
+            true => (&mut self.seeders_v4, &mut self.seeders_v6),
+            false => (&mut self.leechers_v4, &mut self.leechers_v6),
+        }
+    }
+
+    ///Agrega el peer, o lo actualiza si ya estaba (por ejemplo si paso a ser seeder o cambio de direccion)
+    pub fn insert(&mut self, peer_id: PeerId, peer_info: &PeerInfo) {
+        self.remove(&peer_id);
+        let complete = peer_info.is_complete();
+        let (buffer_v4, buffer_v6) = self.get_buffers_mut(complete);
+        let slot = Slot {
+            complete,
+            v4: peer_info
+                .get_sock_addr_v4()
+                .map(|addr| buffer_v4.push(peer_id, &addr.ip().octets(), addr.port())),
+            v6: peer_info
+                .get_sock_addr_v6()
+                .map(|addr| buffer_v6.push(peer_id, &addr.ip().octets(), addr.port())),
+        };
+        self.complete += complete as usize;
+        self.slots.insert(peer_id, slot);
+    }
+
+    pub fn remove(&mut self, peer_id: &PeerId) {
+        let slot = match self.slots.remove(peer_id) {
+            Some(slot) => slot,
+            None => return,
+        };
+        self.complete -= slot.complete as usize;
+        let (buffer_v4, buffer_v6) = self.get_buffers_mut(slot.complete);
+        let moved_v4 = slot
+            .v4
+            .and_then(|position| Some((buffer_v4.swap_remove(position)?, position)));
+        let moved_v6 = slot
+            .v6
+            .and_then(|position| Some((buffer_v6.swap_remove(position)?, position)));
+
+        //Los peers que quedaron en el lugar del eliminado cambiaron de posicion
+        if let Some((moved, position)) = moved_v4 {
+            if let Some(moved_slot) = self.slots.get_mut(&moved) {
+                moved_slot.v4 = Some(position);
+            }
+        }
+        if let Some((moved, position)) = moved_v6 {
+            if let Some(moved_slot) = self.slots.get_mut(&moved) {
+                moved_slot.v6 = Some(position);
+            }
+        }
+    }
+
+    ///Devuelve la cantidad de seeders y de leechers
+    pub fn get_complete_and_incomplete(&self) -> (usize, usize) {
+        (self.complete, self.slots.len() - self.complete)
+    }
+
+    //Devuelve, para cada version de IP, los buffers en el orden en que se eligen los peers para el
+    //solicitante junto con la posicion que ocupa en cada uno, para no devolverlo a el mismo. A los leechers
+    //se les dan primero los seeders y a los seeders primero los leechers.
+    fn get_selection_order(&self, requester: &PeerInfo) -> [[(&PeerBuffer, Option<usize>); 2]; 2] {
+        let requester_slot = self.slots.get(&requester.get_peer_id());
+        let complete = requester.is_complete();
+        let preferred = self.get_buffers(!complete);
+        let others = self.get_buffers(complete);
+        //El solicitante solo puede estar en los buffers de su grupo segun su announce anterior
+        let excluded = |buffers_complete: bool, family: fn(&Slot) -> Option<usize>| {
+            requester_slot
+                .filter(|slot| slot.complete == buffers_complete)
+                .and_then(family)
+        };
+        [
+            [
+                (preferred.0, excluded(!complete, |slot| slot.v4)),
+                (others.0, excluded(complete, |slot| slot.v4)),
+            ],
+            [
+                (preferred.1, excluded(!complete, |slot| slot.v6)),
+                (others.1, excluded(complete, |slot| slot.v6)),
+            ],
+        ]
+    }
+
+    ///Elige al azar hasta `numwant` peers de cada version de IP para el solicitante (sin incluirlo) y los
+    /// devuelve en formato compacto, primero los de IPv4 y luego los de IPv6. A los leechers se les dan
+    /// primero los seeders y a los seeders primero los leechers, completando con el resto si faltan.
+    pub fn select(&self, requester: &PeerInfo, numwant: usize) -> (Vec<u8>, Vec<u8>) {
+        let [order_v4, order_v6] = self.get_selection_order(requester);
+        let mut peers = vec![];
+        let mut peers6 = vec![];
+        for (result, order) in [(&mut peers, order_v4), (&mut peers6, order_v6)] {
+            for (buffer, excluded) in order {
+                let missing = numwant - result.len() / buffer.entry_len;
+                buffer.extend_random(result, missing, excluded);
+            }
+        }
+        (peers, peers6)
+    }
+
+    ///Elige los peers igual que `select`, pero devuelve sus ids en lugar del formato compacto
+    pub fn select_ids(&self, requester: &PeerInfo, numwant: usize) -> (Vec<PeerId>, Vec<PeerId>) {
+        let [order_v4, order_v6] = self.get_selection_order(requester);
+        let mut ids_v4 = vec![];
+        let mut ids_v6 = vec![];
+        for (result, order) in [(&mut ids_v4, order_v4), (&mut ids_v6, order_v6)] {
+            for (buffer, excluded) in order {
+                let missing = numwant - result.len();
+                buffer.extend_random_ids(result, missing, excluded);
+            }
+        }
+        (ids_v4, ids_v6)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tracker::data::{ids::InfoHash, peer_info::Event};
+
+    fn peer(number: u8, left: u64, sock_addr: &str) -> (PeerId, PeerInfo) {
+        let peer_id = PeerId::from_bytes(&[number; 20]).unwrap();
+        let peer_info = PeerInfo::new_from_udp(
+            InfoHash::from_bytes(&[0; 20]).unwrap(),
+            peer_id,
+            6881,
+            0,
+            0,
+            left,
+            Some(Event::Started),
+            None,
+            sock_addr.parse().unwrap(),
+        )
+        .unwrap();
+        (peer_id, peer_info)
+    }
+
+    #[test]
+    fn insert_and_remove_keep_buffers_consistent_ok() {
+        let mut compact_peers = CompactPeers::new();
+        let (first_id, first) = peer(1, 10, "10.0.0.1:1");
+        let (second_id, second) = peer(2, 10, "10.0.0.2:1");
+        let (third_id, third) = peer(3, 0, "[2001:db8::3]:1");
+        compact_peers.insert(first_id, &first);
+        compact_peers.insert(second_id, &second);
+        compact_peers.insert(third_id, &third);
+        assert_eq!((1, 2), compact_peers.get_complete_and_incomplete());
+
+        //El segundo pasa al lugar del primero
+        compact_peers.remove(&first_id);
+        assert_eq!(vec![10, 0, 0, 2, 26, 225], compact_peers.leechers_v4.bytes);
+        assert_eq!(Some(0), compact_peers.slots[&second_id].v4);
+
+        //Al completar la descarga pasa a los seeders
+        let (_, second_completed) = peer(2, 0, "10.0.0.2:1");
+        compact_peers.insert(second_id, &second_completed);
+        assert_eq!(0, compact_peers.leechers_v4.len());
+        assert_eq!(1, compact_peers.seeders_v4.len());
+        assert_eq!(1, compact_peers.seeders_v6.len());
+        assert_eq!((2, 0), compact_peers.get_complete_and_incomplete());
+    }
+
+    #[test]
+    fn select_excludes_requester_and_respects_numwant_ok() {
+        let mut compact_peers = CompactPeers::new();
+        for number in 1..=10 {
+            let (peer_id, peer_info) =
+                peer(number, number as u64 % 2, &format!("10.0.0.{}:1", number));
+            compact_peers.insert(peer_id, &peer_info);
+        }
+        let (requester_id, requester) = peer(1, 1, "10.0.0.1:1");
+        compact_peers.insert(requester_id, &requester);
+
+        let (peers, peers6) = compact_peers.select(&requester, 50);
+        assert_eq!(9 * V4_ENTRY_LEN, peers.len());
+        assert!(peers6.is_empty());
+        assert!(!peers.chunks(V4_ENTRY_LEN).any(|entry| entry[3] == 1));
+
+        //Al leecher se le dan primero los seeders, que son los de numero par
+        let (peers, _) = compact_peers.select(&requester, 3);
+        assert_eq!(3 * V4_ENTRY_LEN, peers.len());
+        assert!(peers.chunks(V4_ENTRY_LEN).all(|entry| entry[3] % 2 == 0));
+    }
+
+    #[test]
+    fn select_returns_ipv6_peers_in_18_bytes_ok() {
+        let mut compact_peers = CompactPeers::new();
+        let (first_id, first) = peer(1, 10, "[2001:db8::1]:6881");
+        let (second_id, second) = peer(2, 10, "10.0.0.2:6881");
+        compact_peers.insert(first_id, &first);
+        compact_peers.insert(second_id, &second);
+        let (_, requester) = peer(3, 10, "10.0.0.3:6881");
+
+        let (peers, peers6) = compact_peers.select(&requester, 50);
+        assert_eq!(vec![10, 0, 0, 2, 26, 225], peers);
+        let mut expected = "2001:db8::1"
+            .parse::<std::net::Ipv6Addr>()
+            .unwrap()
+            .octets()
+            .to_vec();
+        expected.extend_from_slice(&6881u16.to_be_bytes());
+        assert_eq!(V6_ENTRY_LEN, peers6.len());
+        assert_eq!(expected, peers6);
+    }
+}
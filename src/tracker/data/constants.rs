@@ -1,13 +1,72 @@
-use std::ops::RangeInclusive;
-
-pub const GET_URL: &[u8; 16] = b"GET / HTTP/1.1\r\n";
-pub const ANNOUNCE_URL: &[u8; 13] = b"GET /announce";
-pub const CODE_URL: &[u8; 9] = b"GET /code";
-pub const STATS_URL: &[u8; 15] = b"GET /stats.html";
-pub const DOCS_URL: &[u8; 14] = b"GET /docs.html";
-pub const STYLE_URL: &[u8; 10] = b"GET /style";
+pub const GET_METHOD: &str = "GET";
+pub const ROOT_PATH: &str = "/";
+pub const INDEX_PATH: &str = "/index.html";
+pub const ANNOUNCE_PATH: &str = "/announce";
+pub const SCRAPE_PATH: &str = "/scrape";
+pub const CODE_PATH: &str = "/code.js";
+pub const CODE_JS_PATH: &str = "/js/code.js";
+pub const STATS_PATH: &str = "/stats.html";
+pub const DOCS_PATH: &str = "/docs.html";
+pub const STYLE_PATH: &str = "/style.css";
+pub const STATS_DATA_PATH: &str = "/stats/data";
+pub const METRICS_PATH: &str = "/metrics";
 pub const OK_URL: &str = "HTTP/1.1 200 OK";
 pub const ERR_URL: &str = "HTTP/1.1 404 NOT FOUND";
+pub const BAD_REQUEST_URL: &str = "HTTP/1.1 400 BAD REQUEST";
+pub const NOT_ALLOWED_URL: &str = "HTTP/1.1 405 METHOD NOT ALLOWED";
+pub const TOO_LARGE_URL: &str = "HTTP/1.1 431 REQUEST HEADER FIELDS TOO LARGE";
+pub const UNAVAILABLE_URL: &str = "HTTP/1.1 503 SERVICE UNAVAILABLE";
+
+pub const HTTP_VERSION_PREFIX: &str = "HTTP/";
+pub const END_OF_HEADERS: &[u8] = b"\r\n\r\n";
+pub const HTTP_1_1: &str = "HTTP/1.1";
+pub const CONTENT_LENGTH_HEADER: &str = "Content-Length";
+pub const CONNECTION_HEADER: &str = "Connection";
+pub const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+pub const STATIC_ROUTE: &str = "static";
+pub const NOT_FOUND_ROUTE: &str = "not_found";
+pub const INVALID_ROUTE: &str = "invalid";
+pub const KEEP_ALIVE: &str = "keep-alive";
+pub const CLOSE: &str = "close";
+pub const DEFAULT_KEEP_ALIVE_TIMEOUT_SECS: u64 = 5;
+pub const DEFAULT_MAX_REQUESTS_PER_CONNECTION: usize = 100;
+pub const READ_CHUNK_SIZE: usize = 1024;
+pub const MAX_REQUEST_SIZE: usize = 8192;
+pub const DEFAULT_POOL_WORKERS: usize = 4;
+pub const DEFAULT_POOL_QUEUE_SIZE: usize = 64;
+pub const WORKER_POLL_MILLIS: u64 = 10;
+pub const IDLE_POLL_MILLIS: u64 = 500;
+pub const EVENTS_CAPACITY: usize = 1024;
+pub const DEFAULT_SHUTDOWN_TIMEOUT_SECS: u64 = 10;
+pub const EXIT_CODE_ERROR: u8 = 1;
+pub const EXIT_CODE_SHUTDOWN_TIMEOUT: u8 = 2;
+
+//Largo en bytes de los info hash y los peer id
+pub const ID_LEN: usize = 20;
+
+//Cantidad de shards en los que se reparten los torrents, cada uno con su propio lock
+pub const TORRENT_SHARDS: usize = 64;
+
+pub const TRACKER_ADDR_V4: &str = "127.0.0.1:7878";
+pub const TRACKER_ADDR_V6: &str = "[::1]:7878";
+
+pub const CONFIG_PATH: &str = "config.txt";
+pub const CONFIG_KEY: &str = "config";
+pub const FLAG_PREFIX: &str = "--";
+pub const ENV_PREFIX: &str = "TRACKER_";
+pub const STATIC_DIR: &str = ".";
+pub const TORRENTS_DIR: &str = "torrents";
+pub const WHITELIST_PATH: &str = "whitelist.txt";
+pub const SNAPSHOT_PATH: &str = "tracker_state.bencode";
+pub const TEMP_EXTENSION: &str = ".tmp";
+pub const SNAPSHOT_VERSION: i64 = 1;
+pub const DEFAULT_SNAPSHOT_INTERVAL_SECS: u64 = 300;
+pub const STATS_POINTS: u64 = 60;
+pub const STATS_SAMPLE_SECS: u64 = 60;
+pub const TORRENT_EXTENSION: &str = "torrent";
+pub const COMMENT_PREFIX: &str = "#";
+pub const DEFAULT_REGISTRY_RESCAN_SECS: u64 = 300;
+pub const DEFAULT_MAX_TORRENTS: usize = 10000;
 
 pub const INDEX_HTML: &str = "index.html";
 pub const CODE_JS: &str = "js/code.js";
@@ -16,31 +75,77 @@ pub const DOCS_HTML: &str = "docs.html";
 pub const STYLE_CSS: &str = "style.css";
 pub const ERROR_HTML: &str = "404.html";
 
-pub const INFO_HASH: &str = "info_hash=";
-pub const PEER_ID: &str = "peer_id=";
-pub const DOWNLOADED: &str = "downloaded=";
-pub const UPLOADED: &str = "uploaded=";
-pub const LEFT: &str = "left=";
-pub const PORT: &str = "port=";
-pub const EVENT: &str = "event=";
-pub const COMPACT: &str = "compact=";
+pub const INFO_HASH: &str = "info_hash";
+pub const PEER_ID: &str = "peer_id";
+pub const DOWNLOADED: &str = "downloaded";
+pub const UPLOADED: &str = "uploaded";
+pub const LEFT: &str = "left";
+pub const PORT: &str = "port";
+pub const EVENT: &str = "event";
+pub const COMPACT: &str = "compact";
+pub const NUMWANT: &str = "numwant";
+pub const IPV4: &str = "ipv4";
+pub const IPV6: &str = "ipv6";
+pub const RANGE: &str = "range";
 
 pub const STARTED: &str = "started";
 pub const COMPLETED: &str = "completed";
 pub const STOPPED: &str = "stopped";
 
 pub const ZERO: u64 = 0;
-pub const FIRST_PORT: u64 = 6881;
-pub const LAST_PORT: u64 = 6889;
-pub const RANGE_PORT: RangeInclusive<u64> = FIRST_PORT..=LAST_PORT;
 
+pub const DEFAULT_INTERVAL: i64 = 1800;
+pub const DEFAULT_MIN_INTERVAL: i64 = 900;
+pub const DEFAULT_PEER_EXPIRY_FACTOR: u64 = 2;
+pub const DEFAULT_NUMWANT: u64 = 50;
+pub const DEFAULT_MAX_NUMWANT: u64 = 50;
+pub const REAPER_PERIOD_SECS: u64 = 60;
+pub const MAX_FAST_ANNOUNCES: u32 = 3;
+pub const PRIVILEGED_PORT_LIMIT: u16 = 1024;
+
+pub const INFO_BYTES: &[u8] = b"info";
 pub const COMPLETE_BYTES: &[u8] = b"complete";
+pub const DOWNLOADED_BYTES: &[u8] = b"downloaded";
+pub const FILES_BYTES: &[u8] = b"files";
 pub const INCOMPLETE_BYTES: &[u8] = b"incomplete";
 pub const INTERVAL_BYTES: &[u8] = b"interval";
+pub const MIN_INTERVAL_BYTES: &[u8] = b"min interval";
 pub const PEERS_BYTES: &[u8] = b"peers";
+pub const PEERS6_BYTES: &[u8] = b"peers6";
 pub const PEER_ID_BYTES: &[u8] = b"peer_id";
 pub const IP_BYTES: &[u8] = b"ip";
 pub const PORT_BYTES: &[u8] = b"port";
+pub const UPLOADED_BYTES: &[u8] = b"uploaded";
+pub const LEFT_BYTES: &[u8] = b"left";
+pub const COMPACT_BYTES: &[u8] = b"compact";
+pub const EVENT_BYTES: &[u8] = b"event";
+pub const IPV4_BYTES: &[u8] = b"ipv4";
+pub const IPV6_BYTES: &[u8] = b"ipv6";
+pub const INFO_HASH_BYTES: &[u8] = b"info_hash";
+pub const REGISTERED_BYTES: &[u8] = b"registered";
+pub const COMPLETED_BYTES: &[u8] = b"completed";
+pub const TOTAL_DOWNLOADED_BYTES: &[u8] = b"total_downloaded";
+pub const TOTAL_UPLOADED_BYTES: &[u8] = b"total_uploaded";
+pub const LAST_ANNOUNCE_BYTES: &[u8] = b"last_announce";
+pub const VERSION_BYTES: &[u8] = b"version";
+pub const SAVED_AT_BYTES: &[u8] = b"saved_at";
+pub const TORRENTS_BYTES: &[u8] = b"torrents";
+
+//Protocolo UDP (BEP 15)
+pub const UDP_PROTOCOL_ID: u64 = 0x41727101980;
+pub const UDP_ACTION_CONNECT: u32 = 0;
+pub const UDP_ACTION_ANNOUNCE: u32 = 1;
+pub const UDP_ACTION_SCRAPE: u32 = 2;
+pub const UDP_ACTION_ERROR: u32 = 3;
+pub const UDP_EVENT_COMPLETED: u32 = 1;
+pub const UDP_EVENT_STARTED: u32 = 2;
+pub const UDP_EVENT_STOPPED: u32 = 3;
+pub const UDP_CONNECT_LEN: usize = 16;
+pub const UDP_ANNOUNCE_LEN: usize = 98;
+pub const UDP_SCRAPE_MIN_LEN: usize = 16;
+pub const UDP_MAX_SCRAPE_HASHES: usize = 74;
+pub const UDP_CONNECTION_ID_SECS: u64 = 120;
+pub const UDP_MAX_PACKET: usize = 2048;
 
 //[TODO] Mejorar los mensajes de error
 pub const ERROR_INFO_HASH_NOT_FOUND: &str = "you sent me garbage - no info hash";
@@ -51,3 +156,15 @@ pub const ERROR_STAT_NOT_FOUND: &str =
     "you sent me garbage - invalid literal for long() with base 10: ''";
 pub const ERROR_STAT_INVALID: &str = "you sent me garbage - invalid amount";
 pub const ERROR_PORT_INVALID: &str = "you sent me garbage - invalid port";
+pub const ERROR_STATS_RANGE: &str = "{\"error\":\"range must be one of 1h, 5h, 1d or 3d\"}";
+pub const ERROR_MALFORMED_PARAM: &str = "you sent me garbage - invalid encoding";
+pub const ERROR_ANNOUNCE_TOO_FAST: &str =
+    "d14:failure reason41:Announcing too fast, respect min intervale";
+
+pub const ERROR_UDP_CONNECTION_ID: &str = "invalid or expired connection id";
+pub const ERROR_UDP_MALFORMED: &str = "malformed packet";
+pub const ERROR_UDP_ACTION: &str = "unknown action";
+pub const ERROR_UDP_INFO_HASH: &str =
+    "requested download is not authorized for use with this tracker";
+pub const ERROR_UDP_PEER: &str = "invalid announce";
+pub const ERROR_UDP_TOO_FAST: &str = "announcing too fast, respect min interval";
@@ -0,0 +1,120 @@
+//! # Identificadores de torrents y peers
+//! Los info hash y los peer id siempre tienen 20 bytes, por lo que se guardan en arrays de tamaño fijo en
+//! lugar de vectores, asi copiarlos o buscarlos en un diccionario no necesita memoria dinamica. Se pueden
+//! mostrar y parsear tanto en hexadecimal como con urlencoding.
+
+use std::fmt;
+
+use super::constants::ID_LEN;
+use crate::tracker::urlencoding::decoder::try_from_url;
+
+///Decodifica un string hexadecimal (en mayusculas o minusculas) a bytes
+pub fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    //from_str_radix acepta un signo '+' al inicio, por lo que se revisa antes que todos sean digitos
+    if hex.len() % 2 == 1 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|pos| u8::from_str_radix(&hex[pos..pos + 2], 16).ok())
+        .collect()
+}
+
+//Los caracteres que no se codifican en urlencoding (RFC 3986)
+fn is_unreserved(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~')
+}
+
+//Los dos tipos solo se diferencian en el nombre, para no confundir un info hash con un peer id
+macro_rules! impl_id {
+    ($name:ident) => {
+        impl $name {
+            ///Devuelve None si los bytes no son exactamente 20
+            pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+                <[u8; ID_LEN]>::try_from(bytes).ok().map($name)
+            }
+
+            pub fn from_hex(hex: &str) -> Option<Self> {
+                Self::from_bytes(&from_hex(hex)?)
+            }
+
+            ///Parsea el valor tal como llega en un query string, es decir con urlencoding
+            pub fn from_urlencoded(value: &[u8]) -> Option<Self> {
+                Self::from_bytes(&try_from_url(value)?)
+            }
+
+            pub fn as_bytes(&self) -> &[u8] {
+                &self.0
+            }
+
+            pub fn to_hex(&self) -> String {
+                self.0.iter().map(|byte| format!("{:02x}", byte)).collect()
+            }
+
+            ///Devuelve el valor codificado para usarlo en un query string
+            pub fn to_urlencoded(&self) -> String {
+                self.0
+                    .iter()
+                    .map(|byte| match is_unreserved(*byte) {
+                        true => (*byte as char).to_string(),
+                        false => format!("%{:02X}", byte),
+                    })
+                    .collect()
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}", self.to_hex())
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}({})", stringify!($name), self.to_hex())
+            }
+        }
+    };
+}
+
+///SHA-1 del diccionario `info` de un torrent
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct InfoHash([u8; ID_LEN]);
+
+///Identificador que elige cada peer al conectarse
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PeerId([u8; ID_LEN]);
+
+impl_id!(InfoHash);
+impl_id!(PeerId);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_hex_ok() {
+        assert_eq!(Some(vec![0x00, 0xab, 0xff]), from_hex("00abFF"));
+        assert_eq!(None, from_hex("abc"));
+        assert_eq!(None, from_hex("zz"));
+        assert_eq!(None, from_hex("+a"));
+        assert_eq!(None, from_hex("-1"));
+    }
+
+    #[test]
+    fn info_hash_hex_round_trip_ok() {
+        let hex = "0123456789abcdef0123456789abcdef01234567";
+        let info_hash = InfoHash::from_hex(hex).unwrap();
+        assert_eq!(hex, info_hash.to_string());
+        assert_eq!(None, InfoHash::from_hex("0123"));
+    }
+
+    #[test]
+    fn peer_id_urlencoded_round_trip_ok() {
+        let peer_id = PeerId::from_bytes(b"-TR3000-a b\x00\xffcdefghi").unwrap();
+        let encoded = peer_id.to_urlencoded();
+        assert_eq!("-TR3000-a%20b%00%FFcdefghi", encoded);
+        assert_eq!(Some(peer_id), PeerId::from_urlencoded(encoded.as_bytes()));
+        assert_eq!(None, PeerId::from_urlencoded(b"short"));
+    }
+}
@@ -1,3 +1,6 @@
+pub mod compact_peers;
 pub mod constants;
+pub mod ids;
 pub mod peer_info;
+pub mod sharded_torrents;
 pub mod torrent_info;
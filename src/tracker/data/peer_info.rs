@@ -1,5 +1,19 @@
-use super::{super::urlencoding, constants::*};
-use std::net::SocketAddr;
+use super::{
+    super::{
+        bencoding::values::ValuesBencoding,
+        communication::request::HttpRequest,
+        config::TrackerConfig,
+        snapshot::{get_bytes, get_integer},
+        urlencoding::query::{QueryError, QueryParams},
+    },
+    constants::*,
+    ids::{InfoHash, PeerId},
+};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
+    time::{Duration, Instant},
+};
 
 pub enum Event {
     Started,
@@ -7,22 +21,42 @@ pub enum Event {
     Stopped,
 }
 
+#[derive(Debug)]
 pub enum PeerInfoError {
     InfoHashNotFound,
     InfoHashInvalid,
     PeerId,
     PortNotFound,
     PortInvalid,
-    StatNotFound,
-    StatInvalid,
+    StatNotFound(String),
+    StatInvalid(String),
+    MalformedParam(String),
+    AnnounceTooFast,
+}
+
+impl PeerInfoError {
+    ///Devuelve el nombre de la variante, sin el detalle que pueda tener
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            PeerInfoError::InfoHashNotFound => "InfoHashNotFound",
+            PeerInfoError::InfoHashInvalid => "InfoHashInvalid",
+            PeerInfoError::PeerId => "PeerId",
+            PeerInfoError::PortNotFound => "PortNotFound",
+            PeerInfoError::PortInvalid => "PortInvalid",
+            PeerInfoError::StatNotFound(_) => "StatNotFound",
+            PeerInfoError::StatInvalid(_) => "StatInvalid",
+            PeerInfoError::MalformedParam(_) => "MalformedParam",
+            PeerInfoError::AnnounceTooFast => "AnnounceTooFast",
+        }
+    }
 }
 
 pub struct PeerInfo {
     //INGRESADO AL CREAR
     sock_addr: SocketAddr,
     //OBLIGATORIOS DE ANNOUNCE
-    info_hash: Vec<u8>,
-    peer_id: Vec<u8>,
+    info_hash: InfoHash,
+    peer_id: PeerId,
     port: u64,
     downloaded: u64,
     uploaded: u64,
@@ -30,41 +64,22 @@ pub struct PeerInfo {
     //OPCIONALES DE ANNOUNCE
     compact: Option<Vec<u8>>,
     event: Option<Event>,
-}
-
-fn find_index_msg(response: &[u8], size: usize, end_line: &[u8]) -> Option<usize> {
-    let first_pos = response.windows(size).position(|arr| arr == end_line);
-    first_pos.map(|pos| pos + size)
-}
-
-fn take_result(announce: &[u8]) -> Vec<u8> {
-    let mut result = vec![];
-    for &char in announce {
-        if char == b'&' || char == b' ' {
-            break;
-        }
-        result.push(char);
-    }
-    result
-}
-
-fn init_command(announce: &[u8], size_command: usize, command: &str) -> Option<Vec<u8>> {
-    let pos_result = find_index_msg(announce, size_command, command.as_bytes());
-    pos_result.map(|pos| take_result(&announce[pos..]))
+    numwant: Option<u64>,
+    ipv4: Option<SocketAddrV4>,
+    ipv6: Option<SocketAddrV6>,
+    //CONTROL DEL TRACKER
+    last_announce: Instant,
+    fast_announces: u32,
 }
 
 fn from_vec_to_port(result: Option<Vec<u8>>) -> Result<u64, PeerInfoError> {
     match result {
         Some(vec) => {
             let str_port = String::from_utf8_lossy(&vec).to_string();
-            if let Ok(port_num) = str_port.parse::<u64>() {
-                if RANGE_PORT.contains(&port_num) {
-                    Ok(port_num)
-                } else {
-                    Err(PeerInfoError::PortInvalid)
-                }
-            } else {
-                Err(PeerInfoError::PortInvalid)
+            //Los puertos permitidos dependen de la configuracion y se revisan en `check_port_allowed`
+            match str_port.parse::<u16>() {
+                Ok(port_num) if port_num != 0 => Ok(port_num as u64),
+                _ => Err(PeerInfoError::PortInvalid),
             }
         }
         None => Err(PeerInfoError::PortNotFound),
@@ -80,56 +95,41 @@ fn get_event(name_event: String) -> Option<Event> {
     }
 }
 
-fn init_info_hash(announce: &[u8]) -> Result<Vec<u8>, PeerInfoError> {
-    match init_command(announce, INFO_HASH.len(), INFO_HASH) {
-        Some(info_hash_url) => {
-            let url_decoded = urlencoding::decoder::from_url(info_hash_url);
-            if url_decoded.len() != 20 {
-                Err(PeerInfoError::InfoHashInvalid)
-            } else {
-                Ok(url_decoded)
-            }
-        }
+fn init_info_hash(params: &QueryParams) -> Result<InfoHash, PeerInfoError> {
+    match params.get(INFO_HASH) {
+        Some(info_hash) => InfoHash::from_bytes(info_hash).ok_or(PeerInfoError::InfoHashInvalid),
         None => Err(PeerInfoError::InfoHashNotFound),
     }
 }
 
-fn init_peer_id(announce: &[u8]) -> Result<Vec<u8>, PeerInfoError> {
-    match init_command(announce, PEER_ID.len(), PEER_ID) {
-        Some(peer_id_url) => {
-            let url_decoded = urlencoding::decoder::from_url(peer_id_url);
-            if url_decoded.len() != 20 {
-                Err(PeerInfoError::PeerId)
-            } else {
-                Ok(url_decoded)
-            }
-        }
-        None => Err(PeerInfoError::PeerId),
-    }
+fn init_peer_id(params: &QueryParams) -> Result<PeerId, PeerInfoError> {
+    params
+        .get(PEER_ID)
+        .and_then(PeerId::from_bytes)
+        .ok_or(PeerInfoError::PeerId)
 }
 
-fn init_port(announce: &[u8]) -> Result<u64, PeerInfoError> {
-    let port = init_command(announce, PORT.len(), PORT);
+fn init_port(params: &QueryParams) -> Result<u64, PeerInfoError> {
+    let port = params.get(PORT).map(|port| port.to_vec());
     from_vec_to_port(port)
 }
 
-fn init_stat(announce: &[u8], stat_type: &str) -> Result<u64, PeerInfoError> {
-    let stat = init_command(announce, stat_type.len(), stat_type);
-    match stat {
+fn init_stat(params: &QueryParams, stat_type: &str) -> Result<u64, PeerInfoError> {
+    match params.get(stat_type) {
         Some(vec) => {
-            let str_num = String::from_utf8_lossy(&vec).to_string();
+            let str_num = String::from_utf8_lossy(vec).to_string();
             match str_num.parse::<u64>() {
                 Ok(number_res) => Ok(number_res),
-                Err(_) => Err(PeerInfoError::StatInvalid),
+                Err(_) => Err(PeerInfoError::StatInvalid(stat_type.to_owned())),
             }
         }
-        None => Err(PeerInfoError::StatNotFound),
+        None => Err(PeerInfoError::StatNotFound(stat_type.to_owned())),
     }
 }
 
-fn init_event(announce: &[u8]) -> Option<Event> {
-    match init_command(announce, EVENT.len(), EVENT) {
-        Some(vector_event) => match String::from_utf8(vector_event) {
+fn init_event(params: &QueryParams) -> Option<Event> {
+    match params.get(EVENT) {
+        Some(vector_event) => match String::from_utf8(vector_event.to_vec()) {
             Ok(value) => get_event(value),
             Err(_) => None,
         },
@@ -137,36 +137,60 @@ fn init_event(announce: &[u8]) -> Option<Event> {
     }
 }
 
+//El numwant es opcional, pero si se manda tiene que ser un numero valido
+fn init_numwant(params: &QueryParams) -> Result<Option<u64>, PeerInfoError> {
+    match params.get(NUMWANT) {
+        Some(vec) => match String::from_utf8_lossy(vec).parse::<u64>() {
+            Ok(numwant) => Ok(Some(numwant)),
+            Err(_) => Err(PeerInfoError::StatInvalid(NUMWANT.to_owned())),
+        },
+        None => Ok(None),
+    }
+}
+
+//Las direcciones de ipv4=/ipv6= (BEP 7) pueden venir con o sin puerto, si no lo tienen se usa el del announce.
+//Si la direccion es invalida se ignora, como permite el BEP.
+fn init_ipv4(params: &QueryParams, port: u16) -> Option<SocketAddrV4> {
+    let value = std::str::from_utf8(params.get(IPV4)?).ok()?;
+    if let Ok(sock_addr) = value.parse::<SocketAddrV4>() {
+        return Some(sock_addr);
+    }
+    let ip = value.parse::<Ipv4Addr>().ok()?;
+    Some(SocketAddrV4::new(ip, port))
+}
+
+fn init_ipv6(params: &QueryParams, port: u16) -> Option<SocketAddrV6> {
+    let value = std::str::from_utf8(params.get(IPV6)?).ok()?;
+    if let Ok(sock_addr) = value.parse::<SocketAddrV6>() {
+        return Some(sock_addr);
+    }
+    let ip = value.parse::<Ipv6Addr>().ok()?;
+    Some(SocketAddrV6::new(ip, port, 0, 0))
+}
+
+fn parse_query(request: &HttpRequest) -> Result<QueryParams, PeerInfoError> {
+    QueryParams::parse(request.get_query()).map_err(|error| match error {
+        QueryError::Malformed(param) => PeerInfoError::MalformedParam(param),
+    })
+}
+
 impl PeerInfo {
-    pub fn new(announce: Vec<u8>, sock_addr: SocketAddr) -> Result<Self, PeerInfoError> {
+    ///Crea la informacion del peer a partir del query string de un request de announce
+    pub fn new(request: &HttpRequest, sock_addr: SocketAddr) -> Result<Self, PeerInfoError> {
+        let params = parse_query(request)?;
         let mut sock_addr = sock_addr;
         //Si uno de los campos obligatorios del Announce no existe devuelvo error
-        let info_hash = match init_info_hash(&announce) {
-            Ok(result) => result,
-            Err(error) => return Err(error),
-        };
-        let peer_id = match init_peer_id(&announce) {
-            Ok(result) => result,
-            Err(error) => return Err(error),
-        };
-        let port = match init_port(&announce) {
-            Ok(result) => result,
-            Err(error) => return Err(error),
-        };
-        let downloaded = match init_stat(&announce, DOWNLOADED) {
-            Ok(result) => result,
-            Err(error) => return Err(error),
-        };
-        let uploaded = match init_stat(&announce, UPLOADED) {
-            Ok(result) => result,
-            Err(error) => return Err(error),
-        };
-        let left = match init_stat(&announce, LEFT) {
-            Ok(result) => result,
-            Err(error) => return Err(error),
-        };
-        let compact = init_command(&announce, COMPACT.len(), COMPACT);
-        let event = init_event(&announce);
+        let info_hash = init_info_hash(&params)?;
+        let peer_id = init_peer_id(&params)?;
+        let port = init_port(&params)?;
+        let downloaded = init_stat(&params, DOWNLOADED)?;
+        let uploaded = init_stat(&params, UPLOADED)?;
+        let left = init_stat(&params, LEFT)?;
+        let compact = params.get(COMPACT).map(|compact| compact.to_vec());
+        let event = init_event(&params);
+        let numwant = init_numwant(&params)?;
+        let ipv4 = init_ipv4(&params, port as u16);
+        let ipv6 = init_ipv6(&params, port as u16);
 
         //Cambio el puerto dado por el que me dieron en el announce
         sock_addr.set_port(port as u16);
@@ -181,23 +205,191 @@ impl PeerInfo {
             left,
             compact,
             event,
+            numwant,
+            ipv4,
+            ipv6,
+            last_announce: Instant::now(),
+            fast_announces: 0,
         })
     }
 
-    pub fn get_info_hash(&self) -> Vec<u8> {
-        self.info_hash.clone()
+    ///Devuelve el peer si el puerto con el que se anuncio esta permitido en la configuracion del tracker
+    pub fn check_port_allowed(self, config: &TrackerConfig) -> Result<Self, PeerInfoError> {
+        match config.is_port_allowed(self.port as u16) {
+            true => Ok(self),
+            false => Err(PeerInfoError::PortInvalid),
+        }
     }
 
-    pub fn get_peer_id(&self) -> Vec<u8> {
-        self.peer_id.clone()
+    ///Crea la informacion del peer a partir de los campos ya decodificados de un announce del protocolo UDP.
+    /// Las respuestas por UDP son siempre en formato compacto.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new_from_udp(
+        info_hash: InfoHash,
+        peer_id: PeerId,
+        port: u16,
+        downloaded: u64,
+        uploaded: u64,
+        left: u64,
+        event: Option<Event>,
+        numwant: Option<u64>,
+        sock_addr: SocketAddr,
+    ) -> Result<Self, PeerInfoError> {
+        //Los puertos permitidos dependen de la configuracion y se revisan en `check_port_allowed`
+        if port == 0 {
+            return Err(PeerInfoError::PortInvalid);
+        }
+        let mut sock_addr = sock_addr;
+        sock_addr.set_port(port);
+
+        Ok(PeerInfo {
+            sock_addr,
+            info_hash,
+            peer_id,
+            port: port as u64,
+            downloaded,
+            uploaded,
+            left,
+            compact: Some(vec![b'1']),
+            event,
+            numwant,
+            ipv4: None,
+            ipv6: None,
+            last_announce: Instant::now(),
+            fast_announces: 0,
+        })
     }
 
-    pub fn get_port(&self) -> u64 {
-        self.port
+    ///Devuelve el diccionario con el que se guarda el peer en un snapshot. El momento del ultimo announce se
+    /// guarda como tiempo de unix, a partir del tiempo actual dado.
+    pub fn to_snapshot(&self, now: u64) -> ValuesBencoding {
+        let last_announce = now.saturating_sub(self.last_announce.elapsed().as_secs());
+        let mut dic_peer = HashMap::new();
+        dic_peer.insert(
+            PEER_ID_BYTES.to_vec(),
+            ValuesBencoding::String(self.peer_id.as_bytes().to_vec()),
+        );
+        dic_peer.insert(
+            IP_BYTES.to_vec(),
+            ValuesBencoding::String(self.sock_addr.to_string().into_bytes()),
+        );
+        dic_peer.insert(
+            PORT_BYTES.to_vec(),
+            ValuesBencoding::Integer(self.port as i64),
+        );
+        dic_peer.insert(
+            DOWNLOADED_BYTES.to_vec(),
+            ValuesBencoding::Integer(self.downloaded as i64),
+        );
+        dic_peer.insert(
+            UPLOADED_BYTES.to_vec(),
+            ValuesBencoding::Integer(self.uploaded as i64),
+        );
+        dic_peer.insert(
+            LEFT_BYTES.to_vec(),
+            ValuesBencoding::Integer(self.left as i64),
+        );
+        dic_peer.insert(
+            COMPACT_BYTES.to_vec(),
+            ValuesBencoding::Integer(self.is_compact() as i64),
+        );
+        dic_peer.insert(
+            LAST_ANNOUNCE_BYTES.to_vec(),
+            ValuesBencoding::Integer(last_announce as i64),
+        );
+        if self.has_completed_event() {
+            dic_peer.insert(
+                EVENT_BYTES.to_vec(),
+                ValuesBencoding::String(COMPLETED.as_bytes().to_vec()),
+            );
+        }
+        if let Some(ipv4) = self.ipv4 {
+            dic_peer.insert(
+                IPV4_BYTES.to_vec(),
+                ValuesBencoding::String(ipv4.to_string().into_bytes()),
+            );
+        }
+        if let Some(ipv6) = self.ipv6 {
+            dic_peer.insert(
+                IPV6_BYTES.to_vec(),
+                ValuesBencoding::String(ipv6.to_string().into_bytes()),
+            );
+        }
+        ValuesBencoding::Dic(dic_peer)
     }
 
-    pub fn get_sock_addr(&self) -> SocketAddr {
-        self.sock_addr
+    ///Restaura un peer guardado en un snapshot, salvo que su ultimo announce haya sido hace mas tiempo que
+    /// el de expiracion o que el diccionario sea invalido, en cuyo caso se devuelve None.
+    pub fn from_snapshot(
+        info_hash: InfoHash,
+        dic_peer: &HashMap<Vec<u8>, ValuesBencoding>,
+        now: u64,
+        expiry: Duration,
+    ) -> Option<Self> {
+        let last_announce = get_integer(dic_peer, LAST_ANNOUNCE_BYTES)?;
+        let age = Duration::from_secs(now.saturating_sub(u64::try_from(last_announce).ok()?));
+        if age >= expiry {
+            return None;
+        }
+        let parse_addr = |key: &[u8]| std::str::from_utf8(get_bytes(dic_peer, key)?).ok();
+        let get_u64 = |key: &[u8]| u64::try_from(get_integer(dic_peer, key)?).ok();
+        let event = match get_bytes(dic_peer, EVENT_BYTES) {
+            Some(event) if event == COMPLETED.as_bytes() => Some(Event::Completed),
+            _ => None,
+        };
+
+        Some(PeerInfo {
+            sock_addr: parse_addr(IP_BYTES)?.parse().ok()?,
+            info_hash,
+            peer_id: PeerId::from_bytes(get_bytes(dic_peer, PEER_ID_BYTES)?)?,
+            port: get_u64(PORT_BYTES)?,
+            downloaded: get_u64(DOWNLOADED_BYTES)?,
+            uploaded: get_u64(UPLOADED_BYTES)?,
+            left: get_u64(LEFT_BYTES)?,
+            compact: Some(get_u64(COMPACT_BYTES)?.to_string().into_bytes()),
+            event,
+            numwant: None,
+            ipv4: parse_addr(IPV4_BYTES).and_then(|addr| addr.parse().ok()),
+            ipv6: parse_addr(IPV6_BYTES).and_then(|addr| addr.parse().ok()),
+            last_announce: Instant::now().checked_sub(age)?,
+            fast_announces: 0,
+        })
+    }
+
+    pub fn get_info_hash(&self) -> InfoHash {
+        self.info_hash
+    }
+
+    pub fn get_peer_id(&self) -> PeerId {
+        self.peer_id
+    }
+
+    ///Devuelve la direccion IPv4 por la que se puede contactar al peer, ya sea la informada con `ipv4=` o
+    /// la de la conexion (incluso si llego como IPv6 mapeada a IPv4).
+    pub fn get_sock_addr_v4(&self) -> Option<SocketAddrV4> {
+        if self.ipv4.is_some() {
+            return self.ipv4;
+        }
+        match self.sock_addr.ip() {
+            IpAddr::V4(ip) => Some(SocketAddrV4::new(ip, self.sock_addr.port())),
+            IpAddr::V6(ip) => ip
+                .to_ipv4_mapped()
+                .map(|ip| SocketAddrV4::new(ip, self.sock_addr.port())),
+        }
+    }
+
+    ///Devuelve la direccion IPv6 por la que se puede contactar al peer, ya sea la informada con `ipv6=` o
+    /// la de la conexion si la misma no es una IPv4 mapeada.
+    pub fn get_sock_addr_v6(&self) -> Option<SocketAddrV6> {
+        if self.ipv6.is_some() {
+            return self.ipv6;
+        }
+        match self.sock_addr {
+            SocketAddr::V6(sock_addr) if sock_addr.ip().to_ipv4_mapped().is_none() => {
+                Some(sock_addr)
+            }
+            _ => None,
+        }
     }
 
     pub fn get_downloaded_uploaded(&self) -> (u64, u64) {
@@ -211,6 +403,35 @@ impl PeerInfo {
         self.left == ZERO
     }
 
+    ///Devuelve la cantidad de peers que pidio el peer, o la cantidad por defecto si no la indico,
+    /// sin superar el maximo dado
+    pub fn get_numwant(&self, max_numwant: u64) -> usize {
+        self.numwant.unwrap_or(DEFAULT_NUMWANT).min(max_numwant) as usize
+    }
+
+    pub fn get_time_since_last_announce(&self) -> Duration {
+        self.last_announce.elapsed()
+    }
+
+    ///Suma un announce hecho antes del min interval y devuelve cuantos lleva el peer
+    pub fn add_fast_announce(&mut self) -> u32 {
+        self.fast_announces += 1;
+        self.fast_announces
+    }
+
+    ///Indica si el announce es uno de los periodicos, es decir sin ningun evento
+    pub fn is_regular_announce(&self) -> bool {
+        self.event.is_none()
+    }
+
+    pub fn has_started_event(&self) -> bool {
+        matches!(self.event, Some(Event::Started))
+    }
+
+    pub fn has_completed_event(&self) -> bool {
+        matches!(self.event, Some(Event::Completed))
+    }
+
     pub fn is_stopped(&self) -> bool {
         matches!(self.event, Some(Event::Stopped))
     }
@@ -226,14 +447,133 @@ impl PeerInfo {
     }
 }
 
+///Devuelve todos los info_hash (ya decodificados del urlencoding) pasados en un request de scrape,
+/// ignorando los que no tengan 20 bytes. Si no se paso ninguno se devuelve un vector vacio, lo cual
+/// representa un full scrape.
+pub fn get_info_hashes_for_scrape(request: &HttpRequest) -> Result<Vec<InfoHash>, PeerInfoError> {
+    let params = parse_query(request)?;
+    Ok(params
+        .get_all(INFO_HASH)
+        .into_iter()
+        .filter_map(InfoHash::from_bytes)
+        .collect())
+}
+
 pub fn get_error_response_for_announce(error: PeerInfoError) -> String {
     match error {
         PeerInfoError::InfoHashNotFound => ERROR_INFO_HASH_NOT_FOUND.to_owned(),
         PeerInfoError::InfoHashInvalid => ERROR_INFO_HASH_INVALID.to_owned(),
         PeerInfoError::PeerId => ERROR_PEER_ID_INVALID.to_owned(),
-        PeerInfoError::StatNotFound => ERROR_STAT_NOT_FOUND.to_owned(),
-        PeerInfoError::StatInvalid => ERROR_STAT_INVALID.to_owned(),
-        PeerInfoError::PortNotFound => ERROR_STAT_NOT_FOUND.to_owned(),
+        PeerInfoError::StatNotFound(stat) => format!("{} ({})", ERROR_STAT_NOT_FOUND, stat),
+        PeerInfoError::StatInvalid(stat) => format!("{} ({})", ERROR_STAT_INVALID, stat),
+        PeerInfoError::MalformedParam(param) => format!("{} ({})", ERROR_MALFORMED_PARAM, param),
+        PeerInfoError::PortNotFound => format!("{} ({})", ERROR_STAT_NOT_FOUND, PORT),
         PeerInfoError::PortInvalid => ERROR_PORT_INVALID.to_owned(),
+        PeerInfoError::AnnounceTooFast => ERROR_ANNOUNCE_TOO_FAST.to_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ANNOUNCE: &str = "info_hash=abcdefghijklmn123456&peer_id=ABCDEFGHIJKLMNOPQRST&port=6881&downloaded=0&uploaded=0&left=10";
+
+    fn announce(extra: &str, sock_addr: &str) -> PeerInfo {
+        let head = format!("GET /announce?{}{} HTTP/1.1", ANNOUNCE, extra);
+        let request = HttpRequest::parse(head.as_bytes()).unwrap();
+        PeerInfo::new(&request, sock_addr.parse().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn ipv4_mapped_address_is_ipv4_ok() {
+        let peer_info = announce("", "[::ffff:10.0.0.1]:5000");
+        assert_eq!(
+            Some("10.0.0.1:6881".parse().unwrap()),
+            peer_info.get_sock_addr_v4()
+        );
+        assert_eq!(None, peer_info.get_sock_addr_v6());
+    }
+
+    #[test]
+    fn ipv6_address_is_not_ipv4_ok() {
+        let peer_info = announce("", "[2001:db8::1]:5000");
+        assert_eq!(None, peer_info.get_sock_addr_v4());
+        assert_eq!(
+            Some("[2001:db8::1]:6881".parse().unwrap()),
+            peer_info.get_sock_addr_v6()
+        );
+    }
+
+    #[test]
+    fn ipv4_and_ipv6_params_without_port_use_announce_port_ok() {
+        let peer_info = announce("&ipv4=10.0.0.2&ipv6=2001%3Adb8%3A%3A2", "127.0.0.1:5000");
+        assert_eq!(
+            Some("10.0.0.2:6881".parse().unwrap()),
+            peer_info.get_sock_addr_v4()
+        );
+        assert_eq!(
+            Some("[2001:db8::2]:6881".parse().unwrap()),
+            peer_info.get_sock_addr_v6()
+        );
+    }
+
+    #[test]
+    fn ipv4_and_ipv6_params_with_port_ok() {
+        let peer_info = announce(
+            "&ipv4=10.0.0.2%3A7000&ipv6=%5B2001%3Adb8%3A%3A2%5D%3A7001",
+            "127.0.0.1:5000",
+        );
+        assert_eq!(
+            Some("10.0.0.2:7000".parse().unwrap()),
+            peer_info.get_sock_addr_v4()
+        );
+        assert_eq!(
+            Some("[2001:db8::2]:7001".parse().unwrap()),
+            peer_info.get_sock_addr_v6()
+        );
+    }
+
+    #[test]
+    fn invalid_ipv4_and_ipv6_params_are_ignored_ok() {
+        let peer_info = announce("&ipv4=not-an-ip&ipv6=10.0.0.2", "127.0.0.1:5000");
+        assert_eq!(
+            Some("127.0.0.1:6881".parse().unwrap()),
+            peer_info.get_sock_addr_v4()
+        );
+        assert_eq!(None, peer_info.get_sock_addr_v6());
+    }
+
+    #[test]
+    fn numwant_defaults_and_is_clamped_ok() {
+        let peer_info = announce("", "127.0.0.1:5000");
+        assert_eq!(DEFAULT_NUMWANT as usize, peer_info.get_numwant(200));
+        assert_eq!(10, peer_info.get_numwant(10));
+
+        let peer_info = announce("&numwant=30", "127.0.0.1:5000");
+        assert_eq!(30, peer_info.get_numwant(200));
+        assert_eq!(10, peer_info.get_numwant(10));
+    }
+
+    #[test]
+    fn udp_announce_port_ok() {
+        let new_from_udp = |port| {
+            PeerInfo::new_from_udp(
+                InfoHash::from_bytes("abcdefghijklmn123456".as_bytes()).unwrap(),
+                PeerId::from_bytes("ABCDEFGHIJKLMNOPQRST".as_bytes()).unwrap(),
+                port,
+                0,
+                0,
+                10,
+                None,
+                None,
+                "127.0.0.1:5000".parse().unwrap(),
+            )
+        };
+        assert_eq!(
+            Some("127.0.0.1:6881".parse().unwrap()),
+            new_from_udp(6881).unwrap().get_sock_addr_v4()
+        );
+        assert!(matches!(new_from_udp(0), Err(PeerInfoError::PortInvalid)));
     }
 }
@@ -0,0 +1,244 @@
+//! # Diccionario de torrents por shards
+//! Reparte los torrents en varios diccionarios (shards), cada uno con su propio lock, segun el hash de su
+//! info hash. Asi los announces de torrents que estan en distintos shards se atienden en paralelo, en lugar
+//! de esperar todos al mismo lock. Todo lo que se espera para tomar los locks se registra en las metricas.
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    hash::{Hash, Hasher},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+};
+
+use super::{ids::InfoHash, torrent_info::TorrentInfo};
+use crate::{tracker::metrics::Metrics, TrackerError};
+
+pub type Shard = HashMap<InfoHash, TorrentInfo>;
+
+pub struct ShardedTorrents {
+    shards: Vec<RwLock<Shard>>,
+    //Cantidad total de torrents, para no tener que tomar el lock de todos los shards al consultarla
+    len: AtomicUsize,
+    metrics: Arc<Metrics>,
+}
+
+///Lock de escritura sobre un shard. Los torrents se agregan a traves de el para mantener la cantidad total.
+pub struct ShardWriteGuard<'a> {
+    shard: RwLockWriteGuard<'a, Shard>,
+    len: &'a AtomicUsize,
+}
+
+impl<'a> ShardWriteGuard<'a> {
+    pub fn contains_key(&self, info_hash: &InfoHash) -> bool {
+        self.shard.contains_key(info_hash)
+    }
+
+    pub fn get_mut(&mut self, info_hash: &InfoHash) -> Option<&mut TorrentInfo> {
+        self.shard.get_mut(info_hash)
+    }
+
+    pub fn insert(&mut self, torrent: TorrentInfo) {
+        if self
+            .shard
+            .insert(torrent.get_info_hash(), torrent)
+            .is_none()
+        {
+            self.len.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    ///Agrega el torrent solo si la cantidad total de torrents no llego a `max_len`, y devuelve si se agrego
+    pub fn try_insert(&mut self, torrent: TorrentInfo, max_len: usize) -> bool {
+        if self.shard.contains_key(&torrent.get_info_hash()) {
+            return true;
+        }
+        //El lugar se reserva antes de agregarlo para que dos shards no superen el limite a la vez
+        let reserved = self
+            .len
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |len| {
+                (len < max_len).then_some(len + 1)
+            })
+            .is_ok();
+        if reserved {
+            self.shard.insert(torrent.get_info_hash(), torrent);
+        }
+        reserved
+    }
+}
+
+impl ShardedTorrents {
+    ///Crea el diccionario con la cantidad de shards dada (al menos uno) y los torrents dados
+    pub fn new(
+        number_of_shards: usize,
+        torrents: HashMap<InfoHash, TorrentInfo>,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        let sharded = ShardedTorrents {
+            shards: (0..number_of_shards.max(1))
+                .map(|_| RwLock::new(HashMap::new()))
+                .collect(),
+            len: AtomicUsize::new(0),
+            metrics,
+        };
+        for (info_hash, torrent) in torrents {
+            if let Ok(mut shard) = sharded.shards[sharded.get_shard_index(&info_hash)].write() {
+                shard.insert(info_hash, torrent);
+                sharded.len.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        sharded
+    }
+
+    fn get_shard_index(&self, info_hash: &InfoHash) -> usize {
+        let mut hasher = DefaultHasher::new();
+        info_hash.hash(&mut hasher);
+        (hasher.finish() % self.shards.len() as u64) as usize
+    }
+
+    pub fn len(&self) -> usize {
+        self.len.load(Ordering::SeqCst)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    ///Toma el lock de lectura del shard que contiene al info hash dado
+    pub fn read_shard(
+        &self,
+        info_hash: &InfoHash,
+    ) -> Result<RwLockReadGuard<'_, Shard>, TrackerError> {
+        let shard = &self.shards[self.get_shard_index(info_hash)];
+        self.metrics
+            .measure_lock_wait(|| shard.read())
+            .map_err(|_| TrackerError::UnlockingMutexOfTorrents)
+    }
+
+    ///Toma el lock de escritura del shard que contiene al info hash dado
+    pub fn write_shard(&self, info_hash: &InfoHash) -> Result<ShardWriteGuard<'_>, TrackerError> {
+        let shard = &self.shards[self.get_shard_index(info_hash)];
+        let shard = self
+            .metrics
+            .measure_lock_wait(|| shard.write())
+            .map_err(|_| TrackerError::UnlockingMutexOfTorrents)?;
+        Ok(ShardWriteGuard {
+            shard,
+            len: &self.len,
+        })
+    }
+
+    ///Recorre todos los shards de a uno, por lo que no es una vista consistente de todos los torrents a la vez
+    pub fn for_each_shard(&self, mut f: impl FnMut(&Shard)) -> Result<(), TrackerError> {
+        for shard in self.shards.iter() {
+            let shard = self
+                .metrics
+                .measure_lock_wait(|| shard.read())
+                .map_err(|_| TrackerError::UnlockingMutexOfTorrents)?;
+            f(&shard);
+        }
+        Ok(())
+    }
+
+    ///Conserva solo los torrents para los que `f` devuelve true y devuelve cuantos se eliminaron
+    pub fn retain(
+        &self,
+        mut f: impl FnMut(&InfoHash, &mut TorrentInfo) -> bool,
+    ) -> Result<usize, TrackerError> {
+        let mut removed = 0;
+        for shard in self.shards.iter() {
+            let mut shard = self
+                .metrics
+                .measure_lock_wait(|| shard.write())
+                .map_err(|_| TrackerError::UnlockingMutexOfTorrents)?;
+            let previous_len = shard.len();
+            shard.retain(&mut f);
+            removed += previous_len - shard.len();
+            self.len
+                .fetch_sub(previous_len - shard.len(), Ordering::SeqCst);
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tracker::{config::TrackerConfig, stats::SwarmSample};
+
+    fn info_hash(number: u8) -> InfoHash {
+        InfoHash::from_bytes(&[number; 20]).unwrap()
+    }
+
+    fn torrent(number: u8) -> TorrentInfo {
+        TorrentInfo::new(info_hash(number), &TrackerConfig::default())
+    }
+
+    #[test]
+    fn torrents_are_spread_across_shards_ok() {
+        let torrents = (0..100).map(|number| (info_hash(number), torrent(number)));
+        let sharded = ShardedTorrents::new(8, torrents.collect(), Arc::new(Metrics::new()));
+
+        assert_eq!(100, sharded.len());
+        let mut used_shards = 0;
+        sharded
+            .for_each_shard(|shard| used_shards += !shard.is_empty() as usize)
+            .unwrap();
+        assert!(used_shards > 1);
+        assert!(sharded
+            .read_shard(&info_hash(7))
+            .unwrap()
+            .contains_key(&info_hash(7)));
+    }
+
+    #[test]
+    fn try_insert_respects_max_len_ok() {
+        let sharded = ShardedTorrents::new(4, HashMap::new(), Arc::new(Metrics::new()));
+        assert!(sharded
+            .write_shard(&info_hash(1))
+            .unwrap()
+            .try_insert(torrent(1), 2));
+        assert!(sharded
+            .write_shard(&info_hash(2))
+            .unwrap()
+            .try_insert(torrent(2), 2));
+        assert!(!sharded
+            .write_shard(&info_hash(3))
+            .unwrap()
+            .try_insert(torrent(3), 2));
+        //Un torrent que ya esta no cuenta como uno nuevo
+        assert!(sharded
+            .write_shard(&info_hash(1))
+            .unwrap()
+            .try_insert(torrent(1), 2));
+        assert_eq!(2, sharded.len());
+    }
+
+    #[test]
+    fn retain_updates_len_ok() {
+        let torrents = (0..10).map(|number| (info_hash(number), torrent(number)));
+        let sharded = ShardedTorrents::new(4, torrents.collect(), Arc::new(Metrics::new()));
+
+        let removed = sharded
+            .retain(|info_hash, _| info_hash.as_bytes()[0] % 2 == 0)
+            .unwrap();
+        assert_eq!(5, removed);
+        assert_eq!(5, sharded.len());
+    }
+
+    #[test]
+    fn every_lock_acquisition_is_measured_ok() {
+        let metrics = Arc::new(Metrics::new());
+        let sharded = ShardedTorrents::new(4, HashMap::new(), Arc::clone(&metrics));
+
+        //Un lock por cada shard al recorrerlos y al filtrarlos, y uno por cada acceso a un solo shard
+        sharded.for_each_shard(|_| ()).unwrap();
+        sharded.retain(|_, _| true).unwrap();
+        drop(sharded.read_shard(&info_hash(1)).unwrap());
+        drop(sharded.write_shard(&info_hash(1)).unwrap());
+
+        let text = metrics.get_text(SwarmSample::default());
+        assert!(text.contains("tracker_torrents_lock_wait_seconds_count 10\n"));
+    }
+}
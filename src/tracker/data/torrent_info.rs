@@ -1,54 +1,295 @@
 use super::{
     super::bencoding::{encoder::from_dic, values::ValuesBencoding},
+    compact_peers::CompactPeers,
     constants::*,
-    peer_info::PeerInfo,
+    ids::{InfoHash, PeerId},
+    peer_info::{PeerInfo, PeerInfoError},
 };
-use std::collections::HashMap;
+use crate::tracker::{
+    config::TrackerConfig,
+    snapshot::{get_bytes, get_integer, get_list},
+};
+use std::{collections::HashMap, net::SocketAddr, time::Duration};
+
+///Cambios que produjo un announce en las estadisticas del torrent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AnnounceDelta {
+    ///Si el announce conto como una descarga completa (snatch)
+    pub completed: bool,
+    ///Bytes descargados desde el announce anterior del peer
+    pub downloaded: u64,
+    ///Bytes subidos desde el announce anterior del peer
+    pub uploaded: u64,
+}
+
+//Diferencia entre el total informado por un peer y el del announce anterior. Si el total bajo es porque
+//el cliente se reinicio y volvio a contar desde 0, asi que todo lo informado es nuevo.
+fn get_delta(current: u64, previous: u64) -> u64 {
+    current.checked_sub(previous).unwrap_or(current)
+}
 
 pub struct TorrentInfo {
-    info_hash: Vec<u8>,
+    info_hash: InfoHash,
     interval: i64,
-    peers: HashMap<Vec<u8>, PeerInfo>,
+    min_interval: i64,
+    max_numwant: u64,
+    completed: i64,
+    total_downloaded: u64,
+    total_uploaded: u64,
+    registered: bool,
+    peers: HashMap<PeerId, PeerInfo>,
+    //Los mismos peers ya codificados para las respuestas compactas
+    compact_peers: CompactPeers,
 }
 
 impl TorrentInfo {
-    pub fn new(info_hash: Vec<u8>) -> Self {
+    pub fn new(info_hash: InfoHash, config: &TrackerConfig) -> Self {
         let peers = HashMap::new();
-        let interval = 0;
+        let completed = 0;
 
         TorrentInfo {
             info_hash,
-            interval,
+            interval: config.interval,
+            min_interval: config.min_interval,
+            max_numwant: config.max_numwant,
+            completed,
+            total_downloaded: 0,
+            total_uploaded: 0,
+            registered: true,
             peers,
+            compact_peers: CompactPeers::new(),
+        }
+    }
+
+    ///Crea un torrent que no esta en el registro del tracker, sino que se agrego automaticamente en modo
+    /// abierto, por lo que se elimina cuando se queda sin peers.
+    pub fn new_unregistered(info_hash: InfoHash, config: &TrackerConfig) -> Self {
+        let mut torrent = Self::new(info_hash, config);
+        torrent.registered = false;
+        torrent
+    }
+
+    ///Devuelve el diccionario con el que se guarda el torrent (junto con sus peers) en un snapshot
+    pub fn to_snapshot(&self, now: u64) -> ValuesBencoding {
+        let peers = self
+            .peers
+            .values()
+            .map(|peer_info| peer_info.to_snapshot(now))
+            .collect();
+
+        let mut dic_torrent = HashMap::new();
+        dic_torrent.insert(
+            INFO_HASH_BYTES.to_vec(),
+            ValuesBencoding::String(self.info_hash.as_bytes().to_vec()),
+        );
+        dic_torrent.insert(
+            REGISTERED_BYTES.to_vec(),
+            ValuesBencoding::Integer(self.registered as i64),
+        );
+        dic_torrent.insert(
+            COMPLETED_BYTES.to_vec(),
+            ValuesBencoding::Integer(self.completed),
+        );
+        dic_torrent.insert(
+            TOTAL_DOWNLOADED_BYTES.to_vec(),
+            ValuesBencoding::Integer(self.total_downloaded as i64),
+        );
+        dic_torrent.insert(
+            TOTAL_UPLOADED_BYTES.to_vec(),
+            ValuesBencoding::Integer(self.total_uploaded as i64),
+        );
+        dic_torrent.insert(PEERS_BYTES.to_vec(), ValuesBencoding::List(peers));
+        ValuesBencoding::Dic(dic_torrent)
+    }
+
+    ///Restaura un torrent guardado en un snapshot, descartando los peers expirados o invalidos. Los
+    /// intervalos se toman de la configuracion actual y no de la que habia al guardarlo.
+    pub fn from_snapshot(
+        dic_torrent: &HashMap<Vec<u8>, ValuesBencoding>,
+        now: u64,
+        expiry: Duration,
+        config: &TrackerConfig,
+    ) -> Option<Self> {
+        let info_hash = InfoHash::from_bytes(get_bytes(dic_torrent, INFO_HASH_BYTES)?)?;
+        let mut torrent = Self::new(info_hash, config);
+        torrent.registered = get_integer(dic_torrent, REGISTERED_BYTES)? != 0;
+        torrent.completed = get_integer(dic_torrent, COMPLETED_BYTES)?;
+        //Los snapshots guardados antes de contar los bytes transferidos no tienen los totales
+        torrent.total_downloaded = get_integer(dic_torrent, TOTAL_DOWNLOADED_BYTES)
+            .unwrap_or(0)
+            .max(0) as u64;
+        torrent.total_uploaded = get_integer(dic_torrent, TOTAL_UPLOADED_BYTES)
+            .unwrap_or(0)
+            .max(0) as u64;
+
+        for value in get_list(dic_torrent, PEERS_BYTES)? {
+            if let ValuesBencoding::Dic(dic_peer) = value {
+                if let Some(peer_info) = PeerInfo::from_snapshot(info_hash, dic_peer, now, expiry) {
+                    torrent.insert_peer(peer_info.get_peer_id(), peer_info);
+                }
+            }
         }
+        Some(torrent)
+    }
+
+    pub fn is_registered(&self) -> bool {
+        self.registered
+    }
+
+    pub fn set_registered(&mut self, registered: bool) {
+        self.registered = registered;
+    }
+
+    pub fn has_peers(&self) -> bool {
+        !self.peers.is_empty()
     }
 
-    pub fn get_info_hash(&self) -> Vec<u8> {
-        self.info_hash.clone()
+    pub fn get_info_hash(&self) -> InfoHash {
+        self.info_hash
     }
 
-    pub fn add_peer(&mut self, peer_id: Vec<u8>, peer_info: PeerInfo) {
+    //Los peers se agregan y eliminan solo a traves de estas funciones para que los buffers compactos
+    //esten siempre al dia
+    fn insert_peer(&mut self, peer_id: PeerId, peer_info: PeerInfo) {
+        self.compact_peers.insert(peer_id, &peer_info);
         self.peers.insert(peer_id, peer_info);
     }
 
-    fn get_number_of_complete_and_incomplete_peers(&self) -> (i64, i64) {
-        let mut complete = 0;
-        let mut incomplete = 0;
-        for peer in self.peers.values() {
-            if peer.is_complete() {
-                complete += 1;
-            } else {
-                incomplete += 1;
+    fn remove_peer(&mut self, peer_id: &PeerId) {
+        self.compact_peers.remove(peer_id);
+        self.peers.remove(peer_id);
+    }
+
+    ///Agrega o actualiza al peer y acumula lo que transfirio desde su announce anterior. El primer announce
+    /// de un peer (o el primero luego de volver a unirse) solo sirve como punto de partida, ya que sus totales
+    /// pueden incluir lo transferido antes de estar en el swarm. El evento completed solo cuenta como descarga
+    /// si el peer no estaba ya completo. Los peers que avisan que se detienen se sacan del swarm directamente.
+    pub fn add_peer(&mut self, peer_id: PeerId, peer_info: PeerInfo) -> AnnounceDelta {
+        let previous = self.peers.get(&peer_id);
+        let (downloaded, uploaded) = peer_info.get_downloaded_uploaded();
+        let (previous_downloaded, previous_uploaded) = previous
+            .map(|previous| previous.get_downloaded_uploaded())
+            .unwrap_or((downloaded, uploaded));
+        let delta = AnnounceDelta {
+            completed: peer_info.has_completed_event()
+                && !previous
+                    .map(|previous| previous.is_complete())
+                    .unwrap_or(false),
+            downloaded: get_delta(downloaded, previous_downloaded),
+            uploaded: get_delta(uploaded, previous_uploaded),
+        };
+
+        if delta.completed {
+            self.completed += 1;
+        }
+        self.total_downloaded = self.total_downloaded.saturating_add(delta.downloaded);
+        self.total_uploaded = self.total_uploaded.saturating_add(delta.uploaded);
+        if peer_info.is_stopped() {
+            self.remove_peer(&peer_id);
+        } else {
+            self.insert_peer(peer_id, peer_info);
+        }
+        delta
+    }
+
+    ///Verifica que un announce periodico respete el min interval. En caso de no hacerlo se rechaza,
+    /// sin actualizar el momento de su ultimo announce, y si el peer insiste se lo saca del swarm.
+    pub fn check_announce_interval(&mut self, peer_info: &PeerInfo) -> Result<(), PeerInfoError> {
+        if !peer_info.is_regular_announce() {
+            return Ok(());
+        }
+        let peer_id = peer_info.get_peer_id();
+        let min_interval = Duration::from_secs(self.min_interval.max(0) as u64);
+        if let Some(previous) = self.peers.get_mut(&peer_id) {
+            if previous.get_time_since_last_announce() < min_interval {
+                if previous.add_fast_announce() >= MAX_FAST_ANNOUNCES {
+                    self.remove_peer(&peer_id);
+                }
+                return Err(PeerInfoError::AnnounceTooFast);
             }
         }
-        (complete, incomplete)
+        Ok(())
     }
 
-    fn get_response_no_compact(&self, peer_id: Vec<u8>) -> Vec<u8> {
+    ///Elimina los peers que no anunciaron en el tiempo dado y devuelve cuantos se eliminaron
+    pub fn remove_expired_peers(&mut self, expiry: Duration) -> usize {
+        let expired: Vec<PeerId> = self
+            .peers
+            .iter()
+            .filter(|(_, peer_info)| peer_info.get_time_since_last_announce() >= expiry)
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        for peer_id in expired.iter() {
+            self.remove_peer(peer_id);
+        }
+        expired.len()
+    }
+
+    pub fn get_interval(&self) -> i64 {
+        self.interval
+    }
+
+    pub fn get_completed(&self) -> i64 {
+        self.completed
+    }
+
+    ///Devuelve el total de bytes descargados y subidos por los peers del torrent
+    pub fn get_total_downloaded_uploaded(&self) -> (u64, u64) {
+        (self.total_downloaded, self.total_uploaded)
+    }
+
+    pub fn get_number_of_complete_and_incomplete_peers(&self) -> (i64, i64) {
+        let (complete, incomplete) = self.compact_peers.get_complete_and_incomplete();
+        (complete as i64, incomplete as i64)
+    }
+
+    //Devuelve el diccionario con las estadisticas del torrent que se incluye en la respuesta de un scrape
+    pub fn get_scrape_stats(&self) -> ValuesBencoding {
+        let (complete, incomplete) = self.get_number_of_complete_and_incomplete_peers();
+
+        let mut dic_stats: HashMap<Vec<u8>, ValuesBencoding> = HashMap::new();
+        dic_stats.insert(COMPLETE_BYTES.to_vec(), ValuesBencoding::Integer(complete));
+        dic_stats.insert(
+            DOWNLOADED_BYTES.to_vec(),
+            ValuesBencoding::Integer(self.completed),
+        );
+        dic_stats.insert(
+            INCOMPLETE_BYTES.to_vec(),
+            ValuesBencoding::Integer(incomplete),
+        );
+        dic_stats.insert(
+            TOTAL_DOWNLOADED_BYTES.to_vec(),
+            ValuesBencoding::Integer(self.total_downloaded as i64),
+        );
+        dic_stats.insert(
+            TOTAL_UPLOADED_BYTES.to_vec(),
+            ValuesBencoding::Integer(self.total_uploaded as i64),
+        );
+        ValuesBencoding::Dic(dic_stats)
+    }
+
+    fn get_dic_peer(peer_id: &PeerId, sock_addr: SocketAddr) -> ValuesBencoding {
+        let ip = sock_addr.ip().to_string().as_bytes().to_vec();
+        let port = sock_addr.port() as i64;
+
+        let mut dic_peer = HashMap::new();
+        dic_peer.insert(
+            PEER_ID_BYTES.to_vec(),
+            ValuesBencoding::String(peer_id.as_bytes().to_vec()),
+        );
+        dic_peer.insert(IP_BYTES.to_vec(), ValuesBencoding::String(ip));
+        dic_peer.insert(PORT_BYTES.to_vec(), ValuesBencoding::Integer(port));
+        ValuesBencoding::Dic(dic_peer)
+    }
+
+    //Los peers con IPv4 van en la lista "peers" y los que tienen IPv6 en "peers6", un peer que informo
+    //ambas direcciones puede aparecer en las dos. Se eligen igual que los de la respuesta compacta.
+    fn get_response_no_compact(&self, requester: &PeerInfo) -> Vec<u8> {
         let (complete, incomplete) = self.get_number_of_complete_and_incomplete_peers();
 
         let mut dic_to_bencode: HashMap<Vec<u8>, ValuesBencoding> = HashMap::new();
         let mut list_peers: Vec<ValuesBencoding> = vec![];
+        let mut list_peers6: Vec<ValuesBencoding> = vec![];
 
         dic_to_bencode.insert(COMPLETE_BYTES.to_vec(), ValuesBencoding::Integer(complete));
         dic_to_bencode.insert(
@@ -59,37 +300,50 @@ impl TorrentInfo {
             INTERVAL_BYTES.to_vec(),
             ValuesBencoding::Integer(self.interval),
         );
+        dic_to_bencode.insert(
+            MIN_INTERVAL_BYTES.to_vec(),
+            ValuesBencoding::Integer(self.min_interval),
+        );
 
-        for key in self.peers.keys() {
-            if key.clone() == peer_id {
-                continue;
+        let (ids_v4, ids_v6) = self
+            .compact_peers
+            .select_ids(requester, requester.get_numwant(self.max_numwant));
+        for peer_id in ids_v4 {
+            if let Some(sock_addr) = self
+                .peers
+                .get(&peer_id)
+                .and_then(|peer| peer.get_sock_addr_v4())
+            {
+                list_peers.push(Self::get_dic_peer(&peer_id, SocketAddr::V4(sock_addr)));
             }
-            if let Some(peer_info) = self.peers.get(key) {
-                if peer_info.is_stopped() {
-                    continue;
-                }
-                let sock_addr = peer_info.get_sock_addr();
-                let peer_id = key.clone();
-                let ip = sock_addr.ip().to_string().as_bytes().to_vec();
-                let port = sock_addr.port() as i64;
-
-                let mut dic_peer = HashMap::new();
-                dic_peer.insert(PEER_ID_BYTES.to_vec(), ValuesBencoding::String(peer_id));
-                dic_peer.insert(IP_BYTES.to_vec(), ValuesBencoding::String(ip));
-                dic_peer.insert(PORT_BYTES.to_vec(), ValuesBencoding::Integer(port));
-
-                list_peers.push(ValuesBencoding::Dic(dic_peer))
+        }
+        for peer_id in ids_v6 {
+            if let Some(sock_addr) = self
+                .peers
+                .get(&peer_id)
+                .and_then(|peer| peer.get_sock_addr_v6())
+            {
+                list_peers6.push(Self::get_dic_peer(&peer_id, SocketAddr::V6(sock_addr)));
             }
         }
         dic_to_bencode.insert(PEERS_BYTES.to_vec(), ValuesBencoding::List(list_peers));
+        dic_to_bencode.insert(PEERS6_BYTES.to_vec(), ValuesBencoding::List(list_peers6));
         from_dic(dic_to_bencode)
     }
 
-    fn get_response_compact(&self, peer_id: Vec<u8>) -> Vec<u8> {
+    ///Devuelve los peers elegidos para el solicitante en formato compacto, separados en los de IPv4
+    /// (4 bytes de ip y 2 de puerto por cada uno) y los de IPv6 (BEP 7, 16 bytes de ip y 2 de puerto).
+    /// Se copian de los buffers ya codificados, sin recorrer todo el swarm.
+    pub fn get_compact_peers(&self, requester: &PeerInfo) -> (Vec<u8>, Vec<u8>) {
+        self.compact_peers
+            .select(requester, requester.get_numwant(self.max_numwant))
+    }
+
+    fn get_response_compact(&self, requester: &PeerInfo) -> Vec<u8> {
         let (complete, incomplete) = self.get_number_of_complete_and_incomplete_peers();
+        let (peers, peers6) = self.get_compact_peers(requester);
 
         let mut dic_to_bencode: HashMap<Vec<u8>, ValuesBencoding> = HashMap::new();
-        let mut vec_u8_peers = vec![];
 
         dic_to_bencode.insert(COMPLETE_BYTES.to_vec(), ValuesBencoding::Integer(complete));
         dic_to_bencode.insert(
@@ -100,43 +354,311 @@ impl TorrentInfo {
             INTERVAL_BYTES.to_vec(),
             ValuesBencoding::Integer(self.interval),
         );
+        dic_to_bencode.insert(
+            MIN_INTERVAL_BYTES.to_vec(),
+            ValuesBencoding::Integer(self.min_interval),
+        );
+        dic_to_bencode.insert(PEERS_BYTES.to_vec(), ValuesBencoding::String(peers));
+        dic_to_bencode.insert(PEERS6_BYTES.to_vec(), ValuesBencoding::String(peers6));
+        from_dic(dic_to_bencode)
+    }
+
+    //Devuelvo la respuesta en formato bencoding, pido el peer solicitante para no devolverlo a el mismo al
+    //dar la respuesta ya que puede que no sea la primera vez que se comunique y este incluido entre los peers.
+    pub fn get_bencoded_response_for_announce(&self, requester: &PeerInfo) -> Vec<u8> {
+        match requester.is_compact() {
+            true => self.get_response_compact(requester),
+            false => self.get_response_no_compact(requester),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::tracker::{
+        bencoding::decoder::from_torrent_to_dic, data::peer_info::Event, snapshot::get_unix_time,
+    };
+
+    fn get_torrent() -> TorrentInfo {
+        let info_hash = InfoHash::from_bytes(TEST_INFO_HASH.as_bytes()).unwrap();
+        TorrentInfo::new(info_hash, &TrackerConfig::default())
+    }
+
+    pub(crate) const TEST_INFO_HASH: &str = "abcdefghijklmn123456";
+
+    ///Peer anunciado por UDP desde 10.0.0.`number`, con un peer id de 20 bytes iguales a `number`. Es el
+    /// unico constructor de peers de los tests, los demas modulos tambien lo usan.
+    pub(crate) fn test_peer(
+        info_hash: &str,
+        number: u8,
+        event: Option<Event>,
+        left: u64,
+        downloaded: u64,
+        uploaded: u64,
+        numwant: Option<u64>,
+    ) -> PeerInfo {
+        PeerInfo::new_from_udp(
+            InfoHash::from_bytes(info_hash.as_bytes()).unwrap(),
+            PeerId::from_bytes(&[number; 20]).unwrap(),
+            6881,
+            downloaded,
+            uploaded,
+            left,
+            event,
+            numwant,
+            format!("10.0.0.{}:6881", number).parse().unwrap(),
+        )
+        .unwrap()
+    }
+
+    fn peer(number: u8, event: Option<Event>, left: u64) -> PeerInfo {
+        test_peer(TEST_INFO_HASH, number, event, left, 0, 0, None)
+    }
+
+    //Devuelve el mismo peer pero como si su ultimo announce hubiera sido hace `secs` segundos
+    fn aged(peer_info: PeerInfo, secs: u64) -> PeerInfo {
+        let now = get_unix_time();
+        match peer_info.to_snapshot(now - secs) {
+            ValuesBencoding::Dic(dic_peer) => PeerInfo::from_snapshot(
+                peer_info.get_info_hash(),
+                &dic_peer,
+                now,
+                Duration::from_secs(u32::MAX as u64),
+            )
+            .unwrap(),
+            _ => panic!("Peer snapshot is not a dictionary"),
+        }
+    }
+
+    #[test]
+    fn fast_announce_is_rejected_without_updating_last_announce_error() {
+        let mut torrent = get_torrent();
+        let peer_id = PeerId::from_bytes(&[1; 20]).unwrap();
+        torrent.add_peer(peer_id, aged(peer(1, None, 10), 30));
 
-        for key in self.peers.keys() {
-            if key.clone() == peer_id {
-                continue;
+        assert!(matches!(
+            torrent.check_announce_interval(&peer(1, None, 10)),
+            Err(PeerInfoError::AnnounceTooFast)
+        ));
+        assert!(torrent.peers[&peer_id].get_time_since_last_announce() >= Duration::from_secs(30));
+    }
+
+    #[test]
+    fn announce_after_min_interval_ok() {
+        let mut torrent = get_torrent();
+        let peer_id = PeerId::from_bytes(&[1; 20]).unwrap();
+        let min_interval = DEFAULT_MIN_INTERVAL as u64;
+        torrent.add_peer(peer_id, aged(peer(1, None, 10), min_interval));
+
+        assert!(torrent.check_announce_interval(&peer(1, None, 10)).is_ok());
+    }
+
+    #[test]
+    fn peer_is_removed_after_too_many_fast_announces_ok() {
+        let mut torrent = get_torrent();
+        let peer_id = PeerId::from_bytes(&[1; 20]).unwrap();
+        torrent.add_peer(peer_id, peer(1, None, 10));
+
+        for _ in 1..MAX_FAST_ANNOUNCES {
+            assert!(torrent.check_announce_interval(&peer(1, None, 10)).is_err());
+            assert!(torrent.has_peers());
+        }
+        assert!(torrent.check_announce_interval(&peer(1, None, 10)).is_err());
+        assert!(!torrent.has_peers());
+        assert_eq!(
+            (0, 0),
+            torrent.get_number_of_complete_and_incomplete_peers()
+        );
+    }
+
+    #[test]
+    fn announces_with_event_skip_min_interval_ok() {
+        let mut torrent = get_torrent();
+        let peer_id = PeerId::from_bytes(&[1; 20]).unwrap();
+        torrent.add_peer(peer_id, peer(1, None, 10));
+
+        for _ in 0..MAX_FAST_ANNOUNCES {
+            assert!(torrent
+                .check_announce_interval(&peer(1, Some(Event::Started), 10))
+                .is_ok());
+            assert!(torrent
+                .check_announce_interval(&peer(1, Some(Event::Completed), 0))
+                .is_ok());
+            assert!(torrent
+                .check_announce_interval(&peer(1, Some(Event::Stopped), 0))
+                .is_ok());
+        }
+        assert!(torrent.has_peers());
+    }
+
+    #[test]
+    fn expired_peers_are_removed_ok() {
+        let mut torrent = get_torrent();
+        torrent.add_peer(
+            PeerId::from_bytes(&[1; 20]).unwrap(),
+            aged(peer(1, None, 0), 120),
+        );
+        torrent.add_peer(PeerId::from_bytes(&[2; 20]).unwrap(), peer(2, None, 10));
+
+        assert_eq!(1, torrent.remove_expired_peers(Duration::from_secs(60)));
+        assert_eq!(
+            (0, 1),
+            torrent.get_number_of_complete_and_incomplete_peers()
+        );
+        assert_eq!(0, torrent.remove_expired_peers(Duration::from_secs(60)));
+    }
+
+    //Devuelve las listas "peers" y "peers6" de la respuesta no compacta al solicitante
+    fn get_lists_of_peers(
+        torrent: &TorrentInfo,
+        requester: &PeerInfo,
+    ) -> (Vec<ValuesBencoding>, Vec<ValuesBencoding>) {
+        let mut response =
+            from_torrent_to_dic(&torrent.get_response_no_compact(requester)).unwrap();
+        match (response.remove(PEERS_BYTES), response.remove(PEERS6_BYTES)) {
+            (Some(ValuesBencoding::List(peers)), Some(ValuesBencoding::List(peers6))) => {
+                (peers, peers6)
             }
-            if let Some(peer_info) = self.peers.get(key) {
-                if peer_info.is_stopped() {
-                    continue;
-                }
-                let sock_addr = peer_info.get_sock_addr();
-                for ip_num in sock_addr.ip().to_string().split('.') {
-                    if let Ok(ip_num) = ip_num.parse::<u8>() {
-                        vec_u8_peers.push(ip_num);
-                    };
-                }
-                if let Ok(port_num) = sock_addr.port().to_string().parse::<u16>() {
-                    let first_port = port_num / 256;
-                    let second_port = port_num % 256;
-                    vec_u8_peers.push(first_port as u8);
-                    vec_u8_peers.push(second_port as u8);
-                }
+            _ => panic!("Announce response without peers"),
+        }
+    }
+
+    #[test]
+    fn default_numwant_ok() {
+        let mut torrent = get_torrent();
+        for number in 1..=DEFAULT_NUMWANT as u8 + 10 {
+            torrent.add_peer(
+                PeerId::from_bytes(&[number; 20]).unwrap(),
+                peer(number, None, 10),
+            );
+        }
+        let (peers, peers6) = get_lists_of_peers(&torrent, &peer(255, None, 10));
+        assert_eq!(DEFAULT_NUMWANT as usize, peers.len());
+        assert!(peers6.is_empty());
+    }
+
+    #[test]
+    fn numwant_is_clamped_to_max_numwant_ok() {
+        let info_hash = InfoHash::from_bytes(TEST_INFO_HASH.as_bytes()).unwrap();
+        let config = TrackerConfig {
+            max_numwant: 5,
+            ..TrackerConfig::default()
+        };
+        let mut torrent = TorrentInfo::new(info_hash, &config);
+        for number in 1..=20 {
+            torrent.add_peer(
+                PeerId::from_bytes(&[number; 20]).unwrap(),
+                peer(number, None, 10),
+            );
+        }
+
+        let requester = test_peer(TEST_INFO_HASH, 255, None, 10, 0, 0, Some(10));
+        assert_eq!(5, get_lists_of_peers(&torrent, &requester).0.len());
+        let requester = test_peer(TEST_INFO_HASH, 255, None, 10, 0, 0, Some(3));
+        assert_eq!(3, get_lists_of_peers(&torrent, &requester).0.len());
+    }
+
+    #[test]
+    fn leechers_get_seeders_first_ok() {
+        let mut torrent = get_torrent();
+        //Los peers de numero par son seeders
+        for number in 1..=10 {
+            let left = (number % 2) as u64;
+            torrent.add_peer(
+                PeerId::from_bytes(&[number; 20]).unwrap(),
+                peer(number, None, left),
+            );
+        }
+
+        let requester = test_peer(TEST_INFO_HASH, 255, None, 10, 0, 0, Some(5));
+        let (peers, _) = get_lists_of_peers(&torrent, &requester);
+        assert_eq!(5, peers.len());
+        for dic_peer in peers {
+            match dic_peer {
+                ValuesBencoding::Dic(dic_peer) => match dic_peer.get(PEER_ID_BYTES) {
+                    Some(ValuesBencoding::String(peer_id)) => assert_eq!(0, peer_id[0] % 2),
+                    _ => panic!("Peer without id"),
+                },
+                _ => panic!("Peer is not a dictionary"),
             }
         }
-        dic_to_bencode.insert(PEERS_BYTES.to_vec(), ValuesBencoding::String(vec_u8_peers));
-        from_dic(dic_to_bencode)
     }
 
-    //Devuelvo la respuesta en formato bencoding, pido la peer_id solicitante para no devolver la misma al
-    //dar la respuesta ya que puede que no sea la primera vez que se comunique y este incluido entre los peers.
-    pub fn get_bencoded_response_for_announce(
-        &self,
-        peer_id: Vec<u8>,
-        is_compact: bool,
-    ) -> Vec<u8> {
-        match is_compact {
-            true => self.get_response_compact(peer_id),
-            false => self.get_response_no_compact(peer_id),
+    #[test]
+    fn transfer_is_counted_from_previous_announce_ok() {
+        let mut torrent = get_torrent();
+        let peer_id = PeerId::from_bytes(&[1; 20]).unwrap();
+
+        //El primer announce solo marca desde donde se cuenta
+        let delta = torrent.add_peer(
+            peer_id,
+            test_peer(TEST_INFO_HASH, 1, Some(Event::Started), 10, 500, 200, None),
+        );
+        assert_eq!((0, 0), (delta.downloaded, delta.uploaded));
+
+        let delta = torrent.add_peer(
+            peer_id,
+            test_peer(TEST_INFO_HASH, 1, None, 10, 800, 250, None),
+        );
+        assert_eq!((300, 50), (delta.downloaded, delta.uploaded));
+
+        //Si el cliente se reinicio cuenta desde 0, por lo que todo lo informado es nuevo
+        let delta = torrent.add_peer(
+            peer_id,
+            test_peer(TEST_INFO_HASH, 1, None, 10, 100, 20, None),
+        );
+        assert_eq!((100, 20), (delta.downloaded, delta.uploaded));
+        assert_eq!((400, 70), torrent.get_total_downloaded_uploaded());
+    }
+
+    #[test]
+    fn rejoining_peer_starts_a_new_baseline_ok() {
+        let mut torrent = get_torrent();
+        let peer_id = PeerId::from_bytes(&[1; 20]).unwrap();
+        torrent.add_peer(
+            peer_id,
+            test_peer(TEST_INFO_HASH, 1, None, 10, 100, 0, None),
+        );
+        let delta = torrent.add_peer(
+            peer_id,
+            test_peer(TEST_INFO_HASH, 1, Some(Event::Stopped), 10, 300, 0, None),
+        );
+        assert_eq!(200, delta.downloaded);
+        assert!(!torrent.has_peers());
+
+        //Lo transferido mientras no estaba en el swarm no se cuenta
+        let delta = torrent.add_peer(
+            peer_id,
+            test_peer(TEST_INFO_HASH, 1, Some(Event::Started), 10, 1000, 0, None),
+        );
+        assert_eq!(0, delta.downloaded);
+        let delta = torrent.add_peer(
+            peer_id,
+            test_peer(TEST_INFO_HASH, 1, None, 10, 1100, 0, None),
+        );
+        assert_eq!(100, delta.downloaded);
+        assert_eq!((300, 0), torrent.get_total_downloaded_uploaded());
+    }
+
+    #[test]
+    fn repeated_completed_event_counts_one_snatch_ok() {
+        let mut torrent = get_torrent();
+        let peer_id = PeerId::from_bytes(&[1; 20]).unwrap();
+        torrent.add_peer(peer_id, peer(1, Some(Event::Started), 10));
+
+        assert!(
+            torrent
+                .add_peer(peer_id, peer(1, Some(Event::Completed), 0))
+                .completed
+        );
+        for _ in 0..3 {
+            assert!(
+                !torrent
+                    .add_peer(peer_id, peer(1, Some(Event::Completed), 0))
+                    .completed
+            );
         }
+        assert_eq!(1, torrent.get_completed());
     }
 }
@@ -0,0 +1,271 @@
+//! # Modulo de metricas
+//! Cuenta los requests HTTP, los errores de los announces y el tiempo de espera de los locks de los
+//! torrents, y los devuelve junto con el estado del pool y de los swarms en el formato de texto de Prometheus
+
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, PoisonError,
+    },
+    time::Instant,
+};
+
+use super::{
+    data::peer_info::PeerInfoError,
+    stats::SwarmSample,
+    thread_pool::{PoolMonitor, PoolStats},
+};
+
+///Metricas del tracker, que se pueden compartir entre threads
+#[derive(Default)]
+pub struct Metrics {
+    //Cantidad de requests por ruta y codigo de estado de la respuesta
+    requests: Mutex<BTreeMap<(&'static str, &'static str), u64>>,
+    announce_errors: Mutex<BTreeMap<&'static str, u64>>,
+    lock_wait_micros: AtomicU64,
+    lock_acquisitions: AtomicU64,
+    pool: Mutex<Option<PoolMonitor>>,
+}
+
+//Agrega una metrica con su descripcion, su tipo y un valor por cada combinacion de labels
+fn write_metric<T: std::fmt::Display>(
+    text: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    values: &[(String, T)],
+) {
+    let _ = writeln!(text, "# HELP {} {}", name, help);
+    let _ = writeln!(text, "# TYPE {} {}", name, kind);
+    for (labels, value) in values {
+        let _ = writeln!(text, "{}{} {}", name, labels, value);
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    ///Guarda el pool que atiende los requests HTTP para informar su estado
+    pub fn set_pool(&self, monitor: PoolMonitor) {
+        *self.pool.lock().unwrap_or_else(PoisonError::into_inner) = Some(monitor);
+    }
+
+    ///Cuenta un request respondido. El codigo de estado se toma de la status line de la respuesta.
+    pub fn add_request(&self, route: &'static str, status_line: &'static str) {
+        let status = status_line.split(' ').nth(1).unwrap_or(status_line);
+        let mut requests = self.requests.lock().unwrap_or_else(PoisonError::into_inner);
+        *requests.entry((route, status)).or_insert(0) += 1;
+    }
+
+    pub fn add_announce_error(&self, error: &PeerInfoError) {
+        let mut errors = self
+            .announce_errors
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        *errors.entry(error.get_name()).or_insert(0) += 1;
+    }
+
+    ///Toma el lock con la funcion dada y cuenta cuanto hubo que esperar para obtenerlo
+    pub fn measure_lock_wait<T>(&self, lock: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let guard = lock();
+        self.lock_wait_micros
+            .fetch_add(start.elapsed().as_micros() as u64, Ordering::SeqCst);
+        self.lock_acquisitions.fetch_add(1, Ordering::SeqCst);
+        guard
+    }
+
+    ///Devuelve todas las metricas en el formato de texto de Prometheus, con el estado dado de los swarms
+    pub fn get_text(&self, swarms: SwarmSample) -> String {
+        let mut text = String::new();
+
+        let requests: Vec<(String, u64)> = self
+            .requests
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|((route, status), count)| {
+                (
+                    format!("{{route=\"{}\",status=\"{}\"}}", route, status),
+                    *count,
+                )
+            })
+            .collect();
+        write_metric(
+            &mut text,
+            "tracker_http_requests_total",
+            "counter",
+            "HTTP requests answered, by route and status code.",
+            &requests,
+        );
+
+        let errors: Vec<(String, u64)> = self
+            .announce_errors
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(error, count)| (format!("{{error=\"{}\"}}", error), *count))
+            .collect();
+        write_metric(
+            &mut text,
+            "tracker_announce_errors_total",
+            "counter",
+            "Rejected announces, by error.",
+            &errors,
+        );
+
+        let lock_wait = self.lock_wait_micros.load(Ordering::SeqCst) as f64 / 1_000_000.0;
+        let _ = writeln!(
+            text,
+            "# HELP tracker_torrents_lock_wait_seconds Time spent waiting for the lock of a torrents shard."
+        );
+        let _ = writeln!(text, "# TYPE tracker_torrents_lock_wait_seconds summary");
+        let _ = writeln!(text, "tracker_torrents_lock_wait_seconds_sum {}", lock_wait);
+        let _ = writeln!(
+            text,
+            "tracker_torrents_lock_wait_seconds_count {}",
+            self.lock_acquisitions.load(Ordering::SeqCst)
+        );
+
+        let pool = self
+            .pool
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+            .map(|monitor| monitor.get_stats());
+        if let Some(pool) = pool {
+            Self::write_pool(&mut text, pool);
+        }
+
+        for (name, help, value) in [
+            (
+                "tracker_torrents",
+                "Torrents in the tracker.",
+                swarms.torrents,
+            ),
+            ("tracker_seeders", "Seeders in all swarms.", swarms.seeders),
+            (
+                "tracker_leechers",
+                "Leechers in all swarms.",
+                swarms.leechers,
+            ),
+        ] {
+            write_metric(&mut text, name, "gauge", help, &[(String::new(), value)]);
+        }
+        text
+    }
+
+    fn write_pool(text: &mut String, pool: PoolStats) {
+        for (name, kind, help, value) in [
+            (
+                "tracker_pool_workers",
+                "gauge",
+                "Workers in the thread pool.",
+                pool.workers,
+            ),
+            (
+                "tracker_pool_queue_size",
+                "gauge",
+                "Maximum number of queued connections.",
+                pool.queue_size,
+            ),
+            (
+                "tracker_pool_queue_depth",
+                "gauge",
+                "Connections waiting for a free worker.",
+                pool.queued,
+            ),
+            (
+                "tracker_pool_active",
+                "gauge",
+                "Connections being handled by a worker.",
+                pool.active,
+            ),
+            (
+                "tracker_pool_completed_total",
+                "counter",
+                "Connections handled by the pool.",
+                pool.completed,
+            ),
+            (
+                "tracker_pool_rejected_total",
+                "counter",
+                "Connections rejected because the queue was full.",
+                pool.rejected,
+            ),
+            (
+                "tracker_pool_panicked_total",
+                "counter",
+                "Connections whose handler panicked.",
+                pool.panicked,
+            ),
+            (
+                "tracker_pool_respawned_total",
+                "counter",
+                "Workers replaced after their thread died.",
+                pool.respawned,
+            ),
+        ] {
+            write_metric(text, name, kind, help, &[(String::new(), value)]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tracker::thread_pool::ThreadPool;
+
+    #[test]
+    fn requests_are_counted_by_route_and_status_ok() {
+        let metrics = Metrics::new();
+        metrics.add_request("/announce", "HTTP/1.1 200 OK");
+        metrics.add_request("/announce", "HTTP/1.1 200 OK");
+        metrics.add_request("not_found", "HTTP/1.1 404 NOT FOUND");
+
+        let text = metrics.get_text(SwarmSample::default());
+        assert!(
+            text.contains("tracker_http_requests_total{route=\"/announce\",status=\"200\"} 2\n")
+        );
+        assert!(
+            text.contains("tracker_http_requests_total{route=\"not_found\",status=\"404\"} 1\n")
+        );
+    }
+
+    #[test]
+    fn announce_errors_are_counted_by_variant_ok() {
+        let metrics = Metrics::new();
+        metrics.add_announce_error(&PeerInfoError::StatNotFound("left".to_owned()));
+        metrics.add_announce_error(&PeerInfoError::StatNotFound("uploaded".to_owned()));
+        metrics.add_announce_error(&PeerInfoError::PortInvalid);
+
+        let text = metrics.get_text(SwarmSample::default());
+        assert!(text.contains("tracker_announce_errors_total{error=\"StatNotFound\"} 2\n"));
+        assert!(text.contains("tracker_announce_errors_total{error=\"PortInvalid\"} 1\n"));
+    }
+
+    #[test]
+    fn pool_and_swarms_are_reported_ok() {
+        let metrics = Metrics::new();
+        let pool = ThreadPool::new(2, 8);
+        metrics.set_pool(pool.get_monitor());
+        let value = metrics.measure_lock_wait(|| 5);
+        assert_eq!(5, value);
+
+        let text = metrics.get_text(SwarmSample {
+            torrents: 1,
+            seeders: 2,
+            leechers: 3,
+        });
+        assert!(text.contains("tracker_pool_workers 2\n"));
+        assert!(text.contains("tracker_pool_queue_depth 0\n"));
+        assert!(text.contains("tracker_pool_respawned_total 0\n"));
+        assert!(text.contains("tracker_torrents_lock_wait_seconds_count 1\n"));
+        assert!(text.contains("tracker_seeders 2\n"));
+        assert!(text.contains("tracker_leechers 3\n"));
+    }
+}
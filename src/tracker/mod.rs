@@ -1,5 +1,11 @@
 pub mod bencoding;
 pub mod communication;
+pub mod config;
 pub mod data;
+pub mod metrics;
+pub mod registry;
+pub mod shutdown;
+pub mod snapshot;
+pub mod stats;
 pub mod thread_pool;
-mod urlencoding;
+pub mod urlencoding;
@@ -0,0 +1,293 @@
+//! # Modulo de registro de torrents
+//! Se encarga de obtener los info hash de los torrents permitidos en el tracker, ya sea a partir de un
+//! directorio con archivos .torrent o de un archivo de whitelist con un info hash en hexadecimal por linea
+
+use std::{collections::HashSet, fs, path::Path};
+
+use log::{info, warn};
+use sha1::{Digest, Sha1};
+
+use super::{
+    bencoding::{decoder::from_torrent_to_dic_with_spans, values::ValuesBencoding},
+    config::TrackerConfig,
+    data::{
+        constants::*,
+        ids::InfoHash,
+        peer_info::PeerInfo,
+        sharded_torrents::{ShardWriteGuard, ShardedTorrents},
+        torrent_info::TorrentInfo,
+    },
+};
+use crate::{ArcMutexOfTorrents, ResultDyn, TrackerError};
+
+///Calcula el info hash (SHA-1 del diccionario `info` bencodeado) del contenido de un archivo .torrent
+pub fn get_info_hash_of_torrent(torrent_file: Vec<u8>) -> ResultDyn<InfoHash> {
+    let (dic_torrent, spans) = from_torrent_to_dic_with_spans(&torrent_file)?;
+    match (dic_torrent.get(INFO_BYTES), spans.get(INFO_BYTES)) {
+        (Some(ValuesBencoding::Dic(_)), Some(span)) => {
+            //Se hashean los bytes originales del diccionario, ya que volver a encodearlo podria cambiarlos
+            //(por ejemplo si las claves no estaban ordenadas)
+            let mut hasher = Sha1::new();
+            hasher.update(&torrent_file[span.clone()]);
+            InfoHash::from_bytes(&hasher.finalize())
+                .ok_or_else(|| TrackerError::TorrentWithoutInfo.into())
+        }
+        _ => Err(Box::new(TrackerError::TorrentWithoutInfo)),
+    }
+}
+
+fn load_info_hashes_from_dir(torrents_dir: &Path) -> ResultDyn<HashSet<InfoHash>> {
+    let mut info_hashes = HashSet::new();
+    for entry in fs::read_dir(torrents_dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some(TORRENT_EXTENSION) {
+            continue;
+        }
+        match fs::read(&path)
+            .map_err(|err| err.into())
+            .and_then(get_info_hash_of_torrent)
+        {
+            Ok(info_hash) => {
+                info_hashes.insert(info_hash);
+            }
+            Err(error) => warn!("Skipping torrent {}: {}", path.display(), error),
+        }
+    }
+    Ok(info_hashes)
+}
+
+//Cada linea de la whitelist tiene un info hash en hexadecimal, las vacias y las que empiezan con '#' se ignoran
+fn load_info_hashes_from_whitelist(whitelist_path: &Path) -> ResultDyn<HashSet<InfoHash>> {
+    let mut info_hashes = HashSet::new();
+    for line in fs::read_to_string(whitelist_path)?.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(COMMENT_PREFIX) {
+            continue;
+        }
+        match InfoHash::from_hex(line) {
+            Some(info_hash) => {
+                info_hashes.insert(info_hash);
+            }
+            None => warn!("Skipping invalid info hash in whitelist: {}", line),
+        }
+    }
+    Ok(info_hashes)
+}
+
+///Devuelve todos los info hash permitidos segun el directorio de torrents y la whitelist de la configuracion.
+/// Si alguno de los dos no existe simplemente no aporta torrents, pero si existe y no se puede leer se
+/// devuelve error, ya que un registro incompleto haria que se eliminen torrents que siguen permitidos.
+pub fn load_registry(config: &TrackerConfig) -> ResultDyn<HashSet<InfoHash>> {
+    let mut info_hashes = HashSet::new();
+
+    let torrents_dir = Path::new(&config.torrents_dir);
+    if torrents_dir.is_dir() {
+        let from_dir = load_info_hashes_from_dir(torrents_dir)
+            .map_err(|error| format!("Reading {}: {}", torrents_dir.display(), error))?;
+        info_hashes.extend(from_dir);
+    }
+
+    let whitelist_path = Path::new(&config.whitelist_path);
+    if whitelist_path.is_file() {
+        let from_whitelist = load_info_hashes_from_whitelist(whitelist_path)
+            .map_err(|error| format!("Reading {}: {}", whitelist_path.display(), error))?;
+        info_hashes.extend(from_whitelist);
+    }
+
+    if info_hashes.is_empty() {
+        warn!("No torrents registered in the tracker");
+    }
+    Ok(info_hashes)
+}
+
+///Actualiza el diccionario de torrents segun el registro dado: agrega los torrents nuevos (sin peers) y
+/// elimina los que ya no estan permitidos. Los torrents que siguen registrados conservan sus peers, al
+/// igual que los agregados automaticamente en modo abierto.
+pub fn update_torrents(
+    dic_torrents: &ArcMutexOfTorrents,
+    registry: HashSet<InfoHash>,
+    config: &TrackerConfig,
+) -> ResultDyn<()> {
+    let removed = dic_torrents.retain(|info_hash, torrent| {
+        registry.contains(info_hash) || (config.open_tracker && !torrent.is_registered())
+    })?;
+
+    let mut added = 0;
+    for info_hash in registry {
+        let mut shard = dic_torrents.write_shard(&info_hash)?;
+        match shard.get_mut(&info_hash) {
+            Some(torrent) => torrent.set_registered(true),
+            None => {
+                shard.insert(TorrentInfo::new(info_hash, config));
+                added += 1;
+            }
+        }
+    }
+    if added > 0 || removed > 0 {
+        info!(
+            "Torrents registry updated: {} added, {} removed",
+            added, removed
+        );
+    }
+    Ok(())
+}
+
+///Devuelve el torrent al que corresponde el announce, dentro del shard en el que esta su info hash. En modo
+/// abierto, si el torrent no existe y el announce es el primero del peer (evento started), se lo registra
+/// siempre que no se supere el limite de torrents.
+pub fn get_or_register_torrent<'a>(
+    shard: &'a mut ShardWriteGuard<'_>,
+    peer_info: &PeerInfo,
+    config: &TrackerConfig,
+) -> Option<&'a mut TorrentInfo> {
+    let info_hash = peer_info.get_info_hash();
+    if config.open_tracker && peer_info.has_started_event() && !shard.contains_key(&info_hash) {
+        let torrent = TorrentInfo::new_unregistered(info_hash, config);
+        if !shard.try_insert(torrent, config.max_torrents) {
+            warn!("Torrents limit reached, ignoring new info hash");
+            return None;
+        }
+        info!("Registering new torrent {} in open mode", info_hash);
+    }
+    shard.get_mut(&info_hash)
+}
+
+///Elimina los torrents agregados en modo abierto que se quedaron sin peers y devuelve cuantos se eliminaron
+pub fn remove_empty_unregistered_torrents(
+    dic_torrents: &ShardedTorrents,
+) -> Result<usize, TrackerError> {
+    dic_torrents.retain(|_, torrent| torrent.is_registered() || torrent.has_peers())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tracker::{
+        data::{
+            peer_info::Event,
+            torrent_info::tests::{test_peer, TEST_INFO_HASH},
+        },
+        metrics::Metrics,
+    };
+    use std::{collections::HashMap, env, sync::Arc};
+
+    #[test]
+    fn info_hash_of_torrent_ok() {
+        let torrent = "d8:announce3:url4:infod6:lengthi5e4:name4:testee"
+            .as_bytes()
+            .to_vec();
+        let mut hasher = Sha1::new();
+        hasher.update("d6:lengthi5e4:name4:teste".as_bytes());
+        let expected = hasher.finalize().to_vec();
+
+        assert_eq!(
+            expected,
+            get_info_hash_of_torrent(torrent).unwrap().as_bytes()
+        );
+    }
+
+    #[test]
+    fn info_hash_of_torrent_uses_original_bytes_ok() {
+        //Las claves de info no estan ordenadas, por lo que volver a encodearlo daria otro hash
+        let torrent = "d4:infod4:name4:test6:lengthi5ee8:announce3:urle"
+            .as_bytes()
+            .to_vec();
+        let mut hasher = Sha1::new();
+        hasher.update("d4:name4:test6:lengthi5ee".as_bytes());
+        let expected = hasher.finalize().to_vec();
+
+        assert_eq!(
+            expected,
+            get_info_hash_of_torrent(torrent).unwrap().as_bytes()
+        );
+    }
+
+    #[test]
+    fn info_hash_of_torrent_without_info_error() {
+        let torrent = "d8:announce3:urle".as_bytes().to_vec();
+        assert!(get_info_hash_of_torrent(torrent).is_err());
+    }
+
+    fn started_peer(info_hash: &str) -> PeerInfo {
+        test_peer(info_hash, 1, Some(Event::Started), 10, 0, 0, None)
+    }
+
+    #[test]
+    fn open_tracker_registers_unknown_torrent_ok() {
+        let config = TrackerConfig {
+            open_tracker: true,
+            max_torrents: 1,
+            ..TrackerConfig::default()
+        };
+        let dic_torrents =
+            ShardedTorrents::new(TORRENT_SHARDS, HashMap::new(), Arc::new(Metrics::new()));
+
+        let peer_info = started_peer(TEST_INFO_HASH);
+        let mut shard = dic_torrents
+            .write_shard(&peer_info.get_info_hash())
+            .unwrap();
+        assert!(get_or_register_torrent(&mut shard, &peer_info, &config).is_some());
+        drop(shard);
+        assert_eq!(1, dic_torrents.len());
+
+        //Se supera el limite de torrents
+        let peer_info = started_peer("zzzzzzzzzzzzzzzzzzzz");
+        let mut shard = dic_torrents
+            .write_shard(&peer_info.get_info_hash())
+            .unwrap();
+        assert!(get_or_register_torrent(&mut shard, &peer_info, &config).is_none());
+        drop(shard);
+
+        //Al no tener peers se elimina por no estar registrado
+        assert_eq!(
+            1,
+            remove_empty_unregistered_torrents(&dic_torrents).unwrap()
+        );
+    }
+
+    #[test]
+    fn closed_tracker_ignores_unknown_torrent_ok() {
+        let config = TrackerConfig::default();
+        let dic_torrents =
+            ShardedTorrents::new(TORRENT_SHARDS, HashMap::new(), Arc::new(Metrics::new()));
+
+        let peer_info = started_peer(TEST_INFO_HASH);
+        let mut shard = dic_torrents
+            .write_shard(&peer_info.get_info_hash())
+            .unwrap();
+        assert!(get_or_register_torrent(&mut shard, &peer_info, &config).is_none());
+        drop(shard);
+        assert!(dic_torrents.is_empty());
+    }
+
+    fn config_with_whitelist(contents: &[u8], name: &str) -> TrackerConfig {
+        let path = env::temp_dir().join(format!("whitelist_{}_{}.txt", name, std::process::id()));
+        fs::write(&path, contents).unwrap();
+        TrackerConfig {
+            torrents_dir: String::new(),
+            whitelist_path: path.to_string_lossy().to_string(),
+            ..TrackerConfig::default()
+        }
+    }
+
+    #[test]
+    fn load_registry_from_whitelist_ok() {
+        let contents = "# comentario\n6162636465666768696a6b6c6d6e313233343536\n\nnot-a-hash\n";
+        let config = config_with_whitelist(contents.as_bytes(), "ok");
+        let registry = load_registry(&config).unwrap();
+        let _ = fs::remove_file(&config.whitelist_path);
+
+        let expected = InfoHash::from_bytes("abcdefghijklmn123456".as_bytes()).unwrap();
+        assert_eq!(HashSet::from([expected]), registry);
+    }
+
+    #[test]
+    fn load_registry_unreadable_whitelist_error() {
+        //Un archivo que no es UTF-8 no se puede leer como texto
+        let config = config_with_whitelist(&[0xff, 0xfe, 0xfd], "unreadable");
+        let registry = load_registry(&config);
+        let _ = fs::remove_file(&config.whitelist_path);
+
+        assert!(registry.is_err());
+    }
+}
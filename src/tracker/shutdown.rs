@@ -0,0 +1,89 @@
+//! # Modulo de cierre del tracker
+//! Aviso de cierre compartido por todos los hilos del tracker. Ademas de consultarlo se puede esperar a que
+//! se active, por lo que los hilos que no tienen nada que hacer se despiertan apenas se cierra el tracker
+//! en lugar de revisarlo periodicamente.
+
+use std::{
+    sync::{Condvar, Mutex, PoisonError},
+    time::Duration,
+};
+
+#[derive(Default)]
+pub struct GlobalShutdown {
+    is_set: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl GlobalShutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //Si el lock quedo envenenado el bool sigue siendo valido, ya que solo se pasa de false a true
+    pub fn is_set(&self) -> bool {
+        *self.is_set.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    ///Activa el cierre y despierta a todos los hilos que lo estaban esperando
+    pub fn set(&self) {
+        *self.is_set.lock().unwrap_or_else(PoisonError::into_inner) = true;
+        self.condvar.notify_all();
+    }
+
+    ///Bloquea el hilo hasta que se active el cierre
+    pub fn wait(&self) {
+        let is_set = self.is_set.lock().unwrap_or_else(PoisonError::into_inner);
+        let _is_set = self
+            .condvar
+            .wait_while(is_set, |is_set| !*is_set)
+            .unwrap_or_else(PoisonError::into_inner);
+    }
+
+    ///Espera a que se active el cierre como mucho el tiempo dado, y devuelve si se activo
+    pub fn wait_timeout(&self, timeout: Duration) -> bool {
+        let is_set = self.is_set.lock().unwrap_or_else(PoisonError::into_inner);
+        let (is_set, _) = self
+            .condvar
+            .wait_timeout_while(is_set, timeout, |is_set| !*is_set)
+            .unwrap_or_else(PoisonError::into_inner);
+        *is_set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Arc, thread, time::Instant};
+
+    #[test]
+    fn wait_timeout_without_shutdown_ok() {
+        let global_shutdown = GlobalShutdown::new();
+        assert!(!global_shutdown.wait_timeout(Duration::from_millis(10)));
+        assert!(!global_shutdown.is_set());
+    }
+
+    #[test]
+    fn set_wakes_up_waiting_threads_ok() {
+        let global_shutdown = Arc::new(GlobalShutdown::new());
+        let waiters: Vec<_> = (0..2)
+            .map(|_| {
+                let global_shutdown = Arc::clone(&global_shutdown);
+                thread::spawn(move || global_shutdown.wait_timeout(Duration::from_secs(10)))
+            })
+            .collect();
+        let waiter = {
+            let global_shutdown = Arc::clone(&global_shutdown);
+            thread::spawn(move || global_shutdown.wait())
+        };
+
+        let start = Instant::now();
+        thread::sleep(Duration::from_millis(50));
+        global_shutdown.set();
+        for waiter in waiters {
+            assert!(waiter.join().unwrap());
+        }
+        waiter.join().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(global_shutdown.is_set());
+    }
+}
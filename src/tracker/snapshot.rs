@@ -0,0 +1,202 @@
+//! # Modulo de snapshots
+//! Guarda en disco el estado de los swarms (torrents, peers y estadisticas acumuladas) en formato bencoding,
+//! para poder restaurarlo al volver a iniciar el tracker
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::{info, warn};
+
+use super::{
+    bencoding::{decoder::from_torrent_to_dic, encoder::from_dic, values::ValuesBencoding},
+    config::TrackerConfig,
+    data::{constants::*, ids::InfoHash, torrent_info::TorrentInfo},
+};
+use crate::{ArcMutexOfTorrents, ResultDyn};
+
+type DicValues = HashMap<Vec<u8>, ValuesBencoding>;
+
+///Devuelve los segundos transcurridos desde el epoch de unix, que es como se guardan los instantes
+pub fn get_unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
+
+pub fn get_integer(dic: &DicValues, key: &[u8]) -> Option<i64> {
+    match dic.get(key) {
+        Some(ValuesBencoding::Integer(value)) => Some(*value),
+        _ => None,
+    }
+}
+
+pub fn get_bytes<'a>(dic: &'a DicValues, key: &[u8]) -> Option<&'a [u8]> {
+    match dic.get(key) {
+        Some(ValuesBencoding::String(value)) => Some(value),
+        _ => None,
+    }
+}
+
+pub fn get_list<'a>(dic: &'a DicValues, key: &[u8]) -> Option<&'a [ValuesBencoding]> {
+    match dic.get(key) {
+        Some(ValuesBencoding::List(value)) => Some(value),
+        _ => None,
+    }
+}
+
+///Guarda el estado de todos los torrents en el archivo de snapshot de la configuracion. Primero se escribe
+/// un archivo temporal y luego se lo renombra, para no dejar un snapshot a medio escribir. Los shards se
+/// guardan de a uno, por lo que los announces de los demas torrents no se detienen mientras tanto.
+pub fn save_snapshot(dic_torrents: &ArcMutexOfTorrents, config: &TrackerConfig) -> ResultDyn<()> {
+    if config.snapshot_path.is_empty() {
+        return Ok(());
+    }
+    let now = get_unix_time();
+    let mut torrents: Vec<ValuesBencoding> = vec![];
+    dic_torrents.for_each_shard(|shard| {
+        torrents.extend(shard.values().map(|torrent| torrent.to_snapshot(now)))
+    })?;
+    let number_of_torrents = torrents.len();
+
+    let mut dic_snapshot = HashMap::new();
+    dic_snapshot.insert(
+        VERSION_BYTES.to_vec(),
+        ValuesBencoding::Integer(SNAPSHOT_VERSION),
+    );
+    dic_snapshot.insert(
+        SAVED_AT_BYTES.to_vec(),
+        ValuesBencoding::Integer(now as i64),
+    );
+    dic_snapshot.insert(TORRENTS_BYTES.to_vec(), ValuesBencoding::List(torrents));
+
+    let temp_path = format!("{}{}", config.snapshot_path, TEMP_EXTENSION);
+    fs::write(&temp_path, from_dic(dic_snapshot))?;
+    fs::rename(&temp_path, &config.snapshot_path)?;
+    info!(
+        "Saved snapshot of {} torrents in {}",
+        number_of_torrents, config.snapshot_path
+    );
+    Ok(())
+}
+
+///Lee el snapshot de la configuracion y devuelve los torrents guardados en el. Los peers que no anunciaron
+/// dentro del tiempo de expiracion se descartan. Si no hay snapshot se devuelve un diccionario vacio.
+pub fn load_snapshot(config: &TrackerConfig) -> ResultDyn<HashMap<InfoHash, TorrentInfo>> {
+    let mut dic_torrents = HashMap::new();
+    if config.snapshot_path.is_empty() || !Path::new(&config.snapshot_path).is_file() {
+        return Ok(dic_torrents);
+    }
+    let dic_snapshot = from_torrent_to_dic(&fs::read(&config.snapshot_path)?)?;
+    if get_integer(&dic_snapshot, VERSION_BYTES) != Some(SNAPSHOT_VERSION) {
+        warn!("Ignoring snapshot with unknown version");
+        return Ok(dic_torrents);
+    }
+
+    let now = get_unix_time();
+    let expiry = Duration::from_secs(config.get_peer_expiry_secs());
+    for value in get_list(&dic_snapshot, TORRENTS_BYTES).unwrap_or_default() {
+        match value {
+            ValuesBencoding::Dic(dic_torrent) => {
+                match TorrentInfo::from_snapshot(dic_torrent, now, expiry, config) {
+                    Some(torrent) => {
+                        dic_torrents.insert(torrent.get_info_hash(), torrent);
+                    }
+                    None => warn!("Skipping invalid torrent in snapshot"),
+                }
+            }
+            _ => warn!("Skipping invalid torrent in snapshot"),
+        }
+    }
+    info!(
+        "Restored {} torrents from {}",
+        dic_torrents.len(),
+        config.snapshot_path
+    );
+    Ok(dic_torrents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tracker::{
+        data::{
+            peer_info::{Event, PeerInfo},
+            sharded_torrents::ShardedTorrents,
+            torrent_info::tests::{test_peer, TEST_INFO_HASH},
+        },
+        metrics::Metrics,
+    };
+    use std::{env, sync::Arc};
+
+    fn peer(number: u8, left: u64, downloaded: u64, uploaded: u64) -> PeerInfo {
+        test_peer(
+            TEST_INFO_HASH,
+            number,
+            Some(Event::Started),
+            left,
+            downloaded,
+            uploaded,
+            None,
+        )
+    }
+
+    #[test]
+    fn save_and_load_snapshot_ok() {
+        let path = env::temp_dir().join(format!("snapshot_test_{}.bencode", std::process::id()));
+        let config = TrackerConfig {
+            snapshot_path: path.to_string_lossy().to_string(),
+            ..TrackerConfig::default()
+        };
+        let info_hash = InfoHash::from_bytes(TEST_INFO_HASH.as_bytes()).unwrap();
+        let mut torrent = TorrentInfo::new(info_hash, &config);
+        //Cada peer anuncia dos veces para que se acumule lo transferido entre ambos announces
+        for (downloaded, uploaded) in [(10, 20), (20, 40)] {
+            for (number, left) in [(1, 0), (2, 5)] {
+                let peer_info = peer(number, left, downloaded, uploaded);
+                torrent.add_peer(peer_info.get_peer_id(), peer_info);
+            }
+        }
+        let dic_torrents = Arc::new(ShardedTorrents::new(
+            TORRENT_SHARDS,
+            HashMap::from([(info_hash, torrent)]),
+            Arc::new(Metrics::new()),
+        ));
+
+        save_snapshot(&dic_torrents, &config).unwrap();
+        let restored = load_snapshot(&config).unwrap();
+        let _ = fs::remove_file(&path);
+
+        let torrent = restored.get(&info_hash).unwrap();
+        assert!(torrent.is_registered());
+        assert_eq!(
+            (1, 1),
+            torrent.get_number_of_complete_and_incomplete_peers()
+        );
+        assert_eq!((20, 40), torrent.get_total_downloaded_uploaded());
+    }
+
+    #[test]
+    fn load_snapshot_discards_expired_peers_ok() {
+        let config = TrackerConfig::default();
+        let now = get_unix_time();
+        let info_hash = InfoHash::from_bytes(TEST_INFO_HASH.as_bytes()).unwrap();
+        let mut torrent = TorrentInfo::new(info_hash, &config);
+        let peer_info = peer(1, 0, 10, 20);
+        torrent.add_peer(peer_info.get_peer_id(), peer_info);
+
+        //El snapshot se guardo hace mas tiempo que el de expiracion de los peers
+        let saved_at = now - config.get_peer_expiry_secs() - 1;
+        let dic_torrent = match torrent.to_snapshot(saved_at) {
+            ValuesBencoding::Dic(dic_torrent) => dic_torrent,
+            _ => panic!("torrent snapshot is not a dictionary"),
+        };
+        let expiry = Duration::from_secs(config.get_peer_expiry_secs());
+        let restored = TorrentInfo::from_snapshot(&dic_torrent, now, expiry, &config).unwrap();
+        assert!(!restored.has_peers());
+    }
+}
@@ -0,0 +1,279 @@
+//! # Modulo de estadisticas
+//! Registra la actividad del tracker agrupada por minuto (conexiones, announces, descargas completadas, bytes
+//! transferidos y el estado de los swarms) durante los ultimos 3 dias, y la devuelve en JSON para los graficos de stats.html
+
+use std::{
+    collections::VecDeque,
+    sync::{Mutex, PoisonError},
+};
+
+use super::data::{constants::*, sharded_torrents::ShardedTorrents, torrent_info::AnnounceDelta};
+use crate::TrackerError;
+
+///Periodos de tiempo que se pueden consultar
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatsRange {
+    LastHour,
+    LastFiveHours,
+    LastDay,
+    LastThreeDays,
+}
+
+impl StatsRange {
+    ///Parsea el periodo tal como llega en el query string ("1h", "5h", "1d" o "3d")
+    pub fn parse(value: &[u8]) -> Option<Self> {
+        match value {
+            b"1h" => Some(StatsRange::LastHour),
+            b"5h" => Some(StatsRange::LastFiveHours),
+            b"1d" => Some(StatsRange::LastDay),
+            b"3d" => Some(StatsRange::LastThreeDays),
+            _ => None,
+        }
+    }
+
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            StatsRange::LastHour => "1h",
+            StatsRange::LastFiveHours => "5h",
+            StatsRange::LastDay => "1d",
+            StatsRange::LastThreeDays => "3d",
+        }
+    }
+
+    pub fn get_minutes(&self) -> u64 {
+        match self {
+            StatsRange::LastHour => 60,
+            StatsRange::LastFiveHours => 5 * 60,
+            StatsRange::LastDay => 24 * 60,
+            StatsRange::LastThreeDays => 3 * 24 * 60,
+        }
+    }
+}
+
+///Estado de los swarms en un momento dado
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SwarmSample {
+    pub torrents: u64,
+    pub seeders: u64,
+    pub leechers: u64,
+}
+
+impl SwarmSample {
+    ///Cuenta los torrents y los seeders y leechers de todos sus swarms
+    pub fn from_torrents(torrents: &ShardedTorrents) -> Result<Self, TrackerError> {
+        let mut sample = SwarmSample {
+            torrents: torrents.len() as u64,
+            ..SwarmSample::default()
+        };
+        torrents.for_each_shard(|shard| {
+            for torrent in shard.values() {
+                let (complete, incomplete) = torrent.get_number_of_complete_and_incomplete_peers();
+                sample.seeders += complete as u64;
+                sample.leechers += incomplete as u64;
+            }
+        })?;
+        Ok(sample)
+    }
+}
+
+//Lo ocurrido durante un minuto. El estado de los swarms es el ultimo que se tomo en ese minuto, si hubo alguno.
+#[derive(Debug, Clone, Default)]
+struct StatsBucket {
+    minute: u64,
+    connections: u64,
+    announces: u64,
+    completed: u64,
+    downloaded: u64,
+    uploaded: u64,
+    swarms: Option<SwarmSample>,
+}
+
+//Valores de un punto de los graficos, que agrupa uno o mas minutos
+#[derive(Debug, Clone, Default)]
+struct StatsPoint {
+    connections: u64,
+    announces: u64,
+    completed: u64,
+    downloaded: u64,
+    uploaded: u64,
+    swarms: SwarmSample,
+}
+
+///Estadisticas del tracker, que se pueden compartir entre threads
+#[derive(Debug, Default)]
+pub struct Stats {
+    buckets: Mutex<VecDeque<StatsBucket>>,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    //Aplica el cambio dado al minuto correspondiente al tiempo de unix `now`, descartando los que ya no se
+    //pueden consultar
+    fn record(&self, now: u64, change: impl FnOnce(&mut StatsBucket)) {
+        let minute = now / 60;
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        if buckets.back().map(|bucket| bucket.minute) != Some(minute) {
+            buckets.push_back(StatsBucket {
+                minute,
+                ..StatsBucket::default()
+            });
+        }
+        let oldest = minute.saturating_sub(StatsRange::LastThreeDays.get_minutes());
+        while buckets.front().map(|bucket| bucket.minute <= oldest) == Some(true) {
+            buckets.pop_front();
+        }
+        if let Some(bucket) = buckets.back_mut() {
+            change(bucket);
+        }
+    }
+
+    pub fn add_connection(&self, now: u64) {
+        self.record(now, |bucket| bucket.connections += 1);
+    }
+
+    ///Cuenta un announce junto con los cambios que produjo en su torrent
+    pub fn add_announce(&self, now: u64, delta: AnnounceDelta) {
+        self.record(now, |bucket| {
+            bucket.announces += 1;
+            bucket.completed += delta.completed as u64;
+            bucket.downloaded = bucket.downloaded.saturating_add(delta.downloaded);
+            bucket.uploaded = bucket.uploaded.saturating_add(delta.uploaded);
+        });
+    }
+
+    pub fn set_swarms(&self, now: u64, swarms: SwarmSample) {
+        self.record(now, |bucket| bucket.swarms = Some(swarms));
+    }
+
+    //Agrupa los minutos del periodo en STATS_POINTS puntos. Los contadores se suman y para el estado de los
+    //swarms se toma el ultimo del punto, o el del punto anterior si no se tomo ninguno.
+    fn get_points(&self, range: StatsRange, now: u64) -> (u64, Vec<(u64, StatsPoint)>) {
+        let step = range.get_minutes() / STATS_POINTS;
+        let first_minute = (now / 60 + 1).saturating_sub(range.get_minutes());
+        let mut points: Vec<(u64, StatsPoint)> = (0..STATS_POINTS)
+            .map(|index| ((first_minute + index * step) * 60, StatsPoint::default()))
+            .collect();
+        let mut sampled = vec![false; points.len()];
+
+        let buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        for bucket in buckets
+            .iter()
+            .filter(|bucket| bucket.minute >= first_minute)
+        {
+            let index = ((bucket.minute - first_minute) / step) as usize;
+            if let Some((_, point)) = points.get_mut(index) {
+                point.connections += bucket.connections;
+                point.announces += bucket.announces;
+                point.completed += bucket.completed;
+                point.downloaded = point.downloaded.saturating_add(bucket.downloaded);
+                point.uploaded = point.uploaded.saturating_add(bucket.uploaded);
+                if let Some(swarms) = bucket.swarms {
+                    point.swarms = swarms;
+                    sampled[index] = true;
+                }
+            }
+        }
+        for index in 1..points.len() {
+            if !sampled[index] {
+                points[index].1.swarms = points[index - 1].1.swarms;
+            }
+        }
+        (step * 60, points)
+    }
+
+    ///Devuelve en JSON las estadisticas del periodo que termina en el tiempo de unix `now`. Cada campo es
+    /// una lista con un valor por punto, y `timestamps` tiene el tiempo de unix en el que empieza cada punto.
+    pub fn get_json(&self, range: StatsRange, now: u64) -> String {
+        let (step_secs, points) = self.get_points(range, now);
+        let join = |value: fn(&(u64, StatsPoint)) -> u64| {
+            points
+                .iter()
+                .map(|point| value(point).to_string())
+                .collect::<Vec<String>>()
+                .join(",")
+        };
+
+        format!(
+            "{{\"range\":\"{}\",\"step_secs\":{},\"timestamps\":[{}],\"connections\":[{}],\"announces\":[{}],\"completed\":[{}],\"downloaded\":[{}],\"uploaded\":[{}],\"torrents\":[{}],\"seeders\":[{}],\"leechers\":[{}]}}",
+            range.get_name(),
+            step_secs,
+            join(|(time, _)| *time),
+            join(|(_, point)| point.connections),
+            join(|(_, point)| point.announces),
+            join(|(_, point)| point.completed),
+            join(|(_, point)| point.downloaded),
+            join(|(_, point)| point.uploaded),
+            join(|(_, point)| point.swarms.torrents),
+            join(|(_, point)| point.swarms.seeders),
+            join(|(_, point)| point.swarms.leechers),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+
+    #[test]
+    fn counters_are_grouped_by_point_ok() {
+        let stats = Stats::new();
+        stats.add_connection(NOW - 3600);
+        stats.add_connection(NOW - 90);
+        stats.add_connection(NOW);
+        stats.add_announce(
+            NOW,
+            AnnounceDelta {
+                completed: true,
+                downloaded: 100,
+                uploaded: 50,
+            },
+        );
+
+        let (step_secs, points) = stats.get_points(StatsRange::LastHour, NOW);
+        assert_eq!(60, step_secs);
+        assert_eq!(STATS_POINTS as usize, points.len());
+        let total: u64 = points.iter().map(|(_, point)| point.connections).sum();
+        //La primera conexion quedo fuera del periodo
+        assert_eq!(2, total);
+        let (_, last) = points.last().unwrap();
+        assert_eq!(
+            (1, 1, 1),
+            (last.connections, last.announces, last.completed)
+        );
+        assert_eq!((100, 50), (last.downloaded, last.uploaded));
+    }
+
+    #[test]
+    fn swarms_are_carried_forward_ok() {
+        let stats = Stats::new();
+        let swarms = SwarmSample {
+            torrents: 1,
+            seeders: 2,
+            leechers: 3,
+        };
+        stats.set_swarms(NOW - 600, swarms);
+
+        let (_, points) = stats.get_points(StatsRange::LastHour, NOW);
+        assert_eq!(swarms, points.last().unwrap().1.swarms);
+        assert_eq!(SwarmSample::default(), points[0].1.swarms);
+    }
+
+    #[test]
+    fn old_buckets_are_discarded_ok() {
+        let stats = Stats::new();
+        stats.add_announce(NOW - 4 * 24 * 3600, AnnounceDelta::default());
+        stats.add_announce(NOW, AnnounceDelta::default());
+        assert_eq!(1, stats.buckets.lock().unwrap().len());
+    }
+
+    #[test]
+    fn stats_range_parse_ok() {
+        assert_eq!(Some(StatsRange::LastDay), StatsRange::parse(b"1d"));
+        assert_eq!(None, StatsRange::parse(b"2d"));
+    }
+}
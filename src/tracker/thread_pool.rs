@@ -1,15 +1,92 @@
-use log::info;
+use log::{error, info, warn};
 use std::{
+    error::Error,
+    fmt,
+    panic::{self, AssertUnwindSafe},
     sync::{
-        mpsc::{self, Receiver},
-        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, SyncSender, TrySendError},
+        Arc, Mutex, PoisonError,
     },
     thread,
+    time::{Duration, Instant},
 };
 
+use super::data::constants::WORKER_POLL_MILLIS;
+
+///Posibles errores al encolar un trabajo en el pool
+#[derive(Debug, PartialEq, Eq)]
+pub enum ThreadPoolError {
+    ///La cola de trabajos pendientes esta llena
+    QueueFull,
+    ///Los workers ya no reciben trabajos
+    Disconnected,
+    ///Algun worker no termino su trabajo antes del tiempo limite al cerrar el pool
+    ShutdownTimeout,
+}
+
+impl fmt::Display for ThreadPoolError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\n    {:#?}\n", self)
+    }
+}
+
+impl Error for ThreadPoolError {}
+
+///Estado de los trabajos del pool en un momento dado
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PoolStats {
+    pub workers: usize,
+    pub queue_size: usize,
+    pub queued: usize,
+    pub active: usize,
+    pub completed: usize,
+    pub rejected: usize,
+    pub panicked: usize,
+    pub respawned: usize,
+}
+
+//Contadores compartidos entre el pool y sus workers
+#[derive(Default)]
+struct Counters {
+    queued: AtomicUsize,
+    active: AtomicUsize,
+    completed: AtomicUsize,
+    rejected: AtomicUsize,
+    panicked: AtomicUsize,
+    respawned: AtomicUsize,
+}
+
+///Permite consultar el estado del pool desde otros threads sin tener una referencia al pool
+#[derive(Clone)]
+pub struct PoolMonitor {
+    workers: usize,
+    queue_size: usize,
+    counters: Arc<Counters>,
+}
+
+impl PoolMonitor {
+    pub fn get_stats(&self) -> PoolStats {
+        PoolStats {
+            workers: self.workers,
+            queue_size: self.queue_size,
+            queued: self.counters.queued.load(Ordering::SeqCst),
+            active: self.counters.active.load(Ordering::SeqCst),
+            completed: self.counters.completed.load(Ordering::SeqCst),
+            rejected: self.counters.rejected.load(Ordering::SeqCst),
+            panicked: self.counters.panicked.load(Ordering::SeqCst),
+            respawned: self.counters.respawned.load(Ordering::SeqCst),
+        }
+    }
+}
+
 pub struct ThreadPool {
-    workers: Vec<Worker>,
-    sender: mpsc::Sender<Message>,
+    //Los workers estan en un Mutex para poder reemplazar a los que mueran sin necesitar &mut self
+    workers: Mutex<Vec<Worker>>,
+    receiver: Arc<Mutex<Receiver<Message>>>,
+    sender: SyncSender<Message>,
+    queue_size: usize,
+    counters: Arc<Counters>,
 }
 
 struct Worker {
@@ -18,25 +95,34 @@ struct Worker {
 }
 
 impl Worker {
-    fn new(id: usize, receiver: Arc<Mutex<Receiver<Message>>>) -> Worker {
+    fn new(id: usize, receiver: Arc<Mutex<Receiver<Message>>>, counters: Arc<Counters>) -> Worker {
         let thread = Some(thread::spawn(move || loop {
-            let lock = receiver.lock();
-            match lock {
-                Ok(receiver) => {
-                    let message = receiver.recv();
-                    match message {
-                        Ok(Message::NewJob(job)) => {
-                            info!("Worker {} got a job; executing.", id);
-                            job();
-                        }
-                        Ok(Message::Terminate) => {
-                            info!("Worker {} was told to terminate.", id);
-                            break;
-                        }
-                        Err(_) => break, //Ver como informar este error
+            //El lock solo se toma para recibir, y si quedo envenenado el Receiver sigue siendo valido
+            let message = receiver
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .recv();
+            match message {
+                Ok(Message::NewJob(job)) => {
+                    counters.queued.fetch_sub(1, Ordering::SeqCst);
+                    counters.active.fetch_add(1, Ordering::SeqCst);
+                    info!("Worker {} got a job; executing.", id);
+                    //Un panic dentro del trabajo no debe matar al worker
+                    if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                        error!("Worker {} recovered from a panic in a job", id);
+                        counters.panicked.fetch_add(1, Ordering::SeqCst);
                     }
+                    counters.active.fetch_sub(1, Ordering::SeqCst);
+                    counters.completed.fetch_add(1, Ordering::SeqCst);
+                }
+                Ok(Message::Terminate) => {
+                    info!("Worker {} was told to terminate.", id);
+                    break;
                 }
-                Err(_) => break, //Ver como informar este error
+                //Simula un error que mata al worker, fuera del catch_unwind de los trabajos
+                #[cfg(test)]
+                Ok(Message::Panic) => panic!("Worker {} killed", id),
+                Err(_) => break, //El pool se destruyo sin mandar Terminate
             }
         }));
         Worker { id, thread }
@@ -48,43 +134,275 @@ type Job = Box<dyn FnOnce() + Send + 'static>;
 enum Message {
     NewJob(Job),
     Terminate,
+    #[cfg(test)]
+    Panic,
 }
 
 impl ThreadPool {
-    pub fn new(size: usize) -> ThreadPool {
-        let (sender, receiver) = mpsc::channel();
+    ///Crea un pool con `size` workers y una cola de a lo sumo `queue_size` trabajos esperando un worker libre
+    pub fn new(size: usize, queue_size: usize) -> ThreadPool {
+        let (sender, receiver) = mpsc::sync_channel(queue_size);
         let receiver = Arc::new(Mutex::new(receiver));
+        let counters = Arc::new(Counters::default());
         let mut workers = Vec::with_capacity(size);
 
         for id in 0..size {
-            workers.push(Worker::new(id, Arc::clone(&receiver)))
+            workers.push(Worker::new(
+                id,
+                Arc::clone(&receiver),
+                Arc::clone(&counters),
+            ))
         }
 
-        ThreadPool { workers, sender }
+        ThreadPool {
+            workers: Mutex::new(workers),
+            receiver,
+            sender,
+            queue_size,
+            counters,
+        }
     }
 
-    pub fn execute<F>(&self, f: F)
+    ///Encola el trabajo sin bloquearse. Si la cola esta llena el trabajo se descarta y se devuelve un error.
+    pub fn execute<F>(&self, f: F) -> Result<(), ThreadPoolError>
     where
         F: FnOnce() + Send + 'static,
     {
+        self.respawn_dead_workers();
         let job = Box::new(f);
-        let _ = self.sender.send(Message::NewJob(job));
+        //El contador se incrementa antes de enviar para que el worker nunca lo decremente por debajo de 0
+        self.counters.queued.fetch_add(1, Ordering::SeqCst);
+        match self.sender.try_send(Message::NewJob(job)) {
+            Ok(()) => Ok(()),
+            Err(error) => {
+                self.counters.queued.fetch_sub(1, Ordering::SeqCst);
+                self.counters.rejected.fetch_add(1, Ordering::SeqCst);
+                match error {
+                    TrySendError::Full(_) => Err(ThreadPoolError::QueueFull),
+                    TrySendError::Disconnected(_) => Err(ThreadPoolError::Disconnected),
+                }
+            }
+        }
+    }
+
+    //Reemplaza a los workers cuyo thread termino inesperadamente para no perder capacidad
+    fn respawn_dead_workers(&self) {
+        let mut workers = self.workers.lock().unwrap_or_else(PoisonError::into_inner);
+        for worker in workers.iter_mut() {
+            //Un worker sin thread ya fue terminado por el pool, no hay que reemplazarlo
+            let is_dead = matches!(&worker.thread, Some(thread) if thread.is_finished());
+            if is_dead {
+                warn!("Worker {} died, spawning a new one", worker.id);
+                *worker = Worker::new(
+                    worker.id,
+                    Arc::clone(&self.receiver),
+                    Arc::clone(&self.counters),
+                );
+                self.counters.respawned.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    ///Indica si la cola de trabajos pendientes esta llena, en cuyo caso un nuevo trabajo seria rechazado
+    pub fn is_full(&self) -> bool {
+        self.counters.queued.load(Ordering::SeqCst) >= self.queue_size
+    }
+
+    ///Termina el pool dejando que los workers terminen los trabajos en curso y los que estan en la cola.
+    /// Si no terminan antes de `timeout` se los abandona y se devuelve un error.
+    pub fn shutdown(&mut self, timeout: Duration) -> Result<(), ThreadPoolError> {
+        self.terminate_workers(Some(Instant::now() + timeout))
+    }
+
+    //Le avisa a cada worker que termine cuando se vacie la cola y los espera, como mucho hasta el deadline
+    fn terminate_workers(&mut self, deadline: Option<Instant>) -> Result<(), ThreadPoolError> {
+        let workers = self
+            .workers
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        if workers.iter().all(|worker| worker.thread.is_none()) {
+            return Ok(());
+        }
+        let mut timed_out = false;
+
+        for _ in workers.iter().filter(|worker| worker.thread.is_some()) {
+            let mut message = Message::Terminate;
+            loop {
+                match (deadline, self.sender.try_send(message)) {
+                    (_, Ok(())) | (_, Err(TrySendError::Disconnected(_))) => break,
+                    (Some(deadline), Err(TrySendError::Full(_))) if Instant::now() >= deadline => {
+                        timed_out = true;
+                        break;
+                    }
+                    (_, Err(TrySendError::Full(returned))) => {
+                        message = returned;
+                        thread::sleep(Duration::from_millis(WORKER_POLL_MILLIS));
+                    }
+                }
+            }
+        }
+
+        info!("Shutting down all workers");
+
+        for worker in workers.iter_mut() {
+            let thread = match worker.thread.take() {
+                Some(thread) => thread,
+                None => continue,
+            };
+            if let Some(deadline) = deadline {
+                while !thread.is_finished() && Instant::now() < deadline {
+                    thread::sleep(Duration::from_millis(WORKER_POLL_MILLIS));
+                }
+                if !thread.is_finished() {
+                    warn!("Worker {} did not finish in time, abandoning it", worker.id);
+                    timed_out = true;
+                    continue;
+                }
+            }
+            info!("Shutting down worker {}", worker.id);
+            let _ = thread.join();
+        }
+        info!("Thread pool finished: {:?}", self.get_stats());
+
+        match timed_out {
+            true => Err(ThreadPoolError::ShutdownTimeout),
+            false => Ok(()),
+        }
+    }
+
+    pub fn get_monitor(&self) -> PoolMonitor {
+        PoolMonitor {
+            workers: self
+                .workers
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .len(),
+            queue_size: self.queue_size,
+            counters: Arc::clone(&self.counters),
+        }
+    }
+
+    pub fn get_stats(&self) -> PoolStats {
+        self.get_monitor().get_stats()
     }
 }
 
 impl Drop for ThreadPool {
     fn drop(&mut self) {
-        for _ in &self.workers {
-            let _ = self.sender.send(Message::Terminate);
+        let _ = self.terminate_workers(None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn execute_rejects_jobs_when_queue_is_full_ok() {
+        let pool = ThreadPool::new(1, 1);
+        let (block_sender, block_receiver) = channel::<()>();
+        let (started_sender, started_receiver) = channel();
+
+        //El unico worker queda ocupado hasta que se cierre el canal
+        pool.execute(move || {
+            let _ = started_sender.send(());
+            let _ = block_receiver.recv();
+        })
+        .unwrap();
+        started_receiver.recv().unwrap();
+
+        assert_eq!(Ok(()), pool.execute(|| ()));
+        assert!(pool.is_full());
+        assert_eq!(Err(ThreadPoolError::QueueFull), pool.execute(|| ()));
+
+        let stats = pool.get_stats();
+        assert_eq!((1, 1, 1), (stats.queued, stats.active, stats.rejected));
+        drop(block_sender);
+    }
+
+    #[test]
+    fn jobs_run_concurrently_ok() {
+        let pool = ThreadPool::new(2, 4);
+        let (first_sender, first_receiver) = channel();
+        let (second_sender, second_receiver) = channel();
+
+        //Cada trabajo espera al otro, por lo que solo terminan si se ejecutan a la vez
+        pool.execute(move || {
+            first_sender.send(()).unwrap();
+            second_receiver.recv().unwrap();
+        })
+        .unwrap();
+        pool.execute(move || {
+            first_receiver.recv().unwrap();
+            second_sender.send(()).unwrap();
+        })
+        .unwrap();
+
+        drop(pool);
+    }
+
+    #[test]
+    fn worker_survives_a_panicking_job_ok() {
+        let pool = ThreadPool::new(1, 2);
+        let (sender, receiver) = channel();
+
+        pool.execute(|| panic!("job failed")).unwrap();
+        pool.execute(move || sender.send(()).unwrap()).unwrap();
+
+        receiver.recv().unwrap();
+        assert_eq!(1, pool.get_stats().panicked);
+        assert_eq!(0, pool.get_stats().respawned);
+    }
+
+    #[test]
+    fn dead_worker_is_respawned_ok() {
+        let pool = ThreadPool::new(1, 2);
+        pool.sender.send(Message::Panic).unwrap();
+        let start = Instant::now();
+        while !pool.workers.lock().unwrap()[0]
+            .thread
+            .as_ref()
+            .unwrap()
+            .is_finished()
+        {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            thread::sleep(Duration::from_millis(WORKER_POLL_MILLIS));
         }
 
-        info!("Shutting down all workers");
+        //Sin el unico worker el trabajo solo se ejecuta si se lo reemplaza
+        let (sender, receiver) = channel();
+        pool.execute(move || sender.send(()).unwrap()).unwrap();
+        receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(1, pool.get_stats().respawned);
+        assert_eq!(0, pool.get_stats().panicked);
+    }
 
-        for worker in &mut self.workers {
-            info!("Shutting down worker {}", worker.id);
-            if let Some(thread) = worker.thread.take() {
-                let _ = thread.join();
-            }
+    #[test]
+    fn shutdown_finishes_queued_jobs_ok() {
+        let mut pool = ThreadPool::new(1, 4);
+        let (sender, receiver) = channel();
+        for _ in 0..3 {
+            let sender = sender.clone();
+            pool.execute(move || sender.send(()).unwrap()).unwrap();
         }
+
+        assert_eq!(Ok(()), pool.shutdown(Duration::from_secs(5)));
+        assert_eq!(3, receiver.try_iter().count());
+    }
+
+    #[test]
+    fn shutdown_error_timeout() {
+        let mut pool = ThreadPool::new(1, 1);
+        let (_block_sender, block_receiver) = channel::<()>();
+        pool.execute(move || {
+            let _ = block_receiver.recv();
+        })
+        .unwrap();
+
+        assert_eq!(
+            Err(ThreadPoolError::ShutdownTimeout),
+            pool.shutdown(Duration::from_millis(100))
+        );
     }
 }
@@ -29,10 +29,46 @@ pub fn from_url(url: Vec<u8>) -> Vec<u8> {
     vec_res
 }
 
+/// Version estricta de [from_url]: en lugar de ignorar los percent-encoding invalidos (un '%' que no
+/// esta seguido de dos digitos hexadecimales) devuelve None.
+///
+pub fn try_from_url(url: &[u8]) -> Option<Vec<u8>> {
+    let mut vec_res = Vec::with_capacity(url.len());
+    let mut pos = 0;
+    while pos < url.len() {
+        if url[pos] == b'%' {
+            let hex = url.get(pos + 1..pos + 3)?;
+            if !hex.iter().all(|byte| byte.is_ascii_hexdigit()) {
+                return None;
+            }
+            vec_res.push(u8::from_str_radix(std::str::from_utf8(hex).ok()?, 16).ok()?);
+            pos += 3;
+        } else {
+            vec_res.push(url[pos]);
+            pos += 1;
+        }
+    }
+    Some(vec_res)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn strict_decoding_ok() {
+        let result = try_from_url("%ac%C3A%20".as_bytes());
+        assert_eq!(Some(vec![0xACu8, 0xC3u8, b'A', b' ']), result);
+    }
+
+    #[test]
+    fn strict_decoding_invalid_percent_fails() {
+        assert_eq!(None, try_from_url("%".as_bytes()));
+        assert_eq!(None, try_from_url("abc%2".as_bytes()));
+        assert_eq!(None, try_from_url("%g0".as_bytes()));
+        assert_eq!(None, try_from_url("%+1".as_bytes()));
+    }
+
     #[test]
     fn percent20_decodes_to_space_ok() {
         let encoded_space = String::from("%20").as_bytes().to_vec();
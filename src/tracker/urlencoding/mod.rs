@@ -1,2 +1,3 @@
 pub mod decoder;
+pub mod query;
 // pub mod encoder;  //PARA CUANDO SE PASE AL OTRO REPO
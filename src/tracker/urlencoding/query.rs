@@ -0,0 +1,129 @@
+//! # Modulo de Query strings
+//! Modulo que contiene el parser de los query strings (`clave=valor&clave=valor`) de los requests,
+//! decodificando el percent-encoding tanto de las claves como de los valores.
+
+use std::{error::Error, fmt};
+
+use super::decoder::try_from_url;
+
+///Error al parsear un query string, contiene el nombre del parametro mal formado
+#[derive(Debug, PartialEq, Eq)]
+pub enum QueryError {
+    Malformed(String),
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\n    {:#?}\n", self)
+    }
+}
+
+impl Error for QueryError {}
+
+///Parametros de un query string ya decodificados, en el mismo orden en el que llegaron
+#[derive(Debug, PartialEq, Eq)]
+pub struct QueryParams {
+    params: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+//En los query strings el '+' representa un espacio, se reemplaza antes de decodificar para que un
+//"%2B" siga siendo un '+'
+fn decode_component(component: &[u8]) -> Option<Vec<u8>> {
+    let with_spaces: Vec<u8> = component
+        .iter()
+        .map(|&byte| if byte == b'+' { b' ' } else { byte })
+        .collect();
+    try_from_url(&with_spaces)
+}
+
+impl QueryParams {
+    ///Parsea el query string (sin el '?' inicial). Los parametros vacios se ignoran y los que no tienen
+    /// '=' se toman con valor vacio. Si alguna clave o valor tiene un percent-encoding invalido se
+    /// devuelve el error con el nombre de ese parametro.
+    pub fn parse(query: &[u8]) -> Result<Self, QueryError> {
+        let mut params = vec![];
+        for param in query.split(|&byte| byte == b'&') {
+            if param.is_empty() {
+                continue;
+            }
+            let (key, value) = match param.iter().position(|&byte| byte == b'=') {
+                Some(pos) => (&param[..pos], &param[pos + 1..]),
+                None => (param, &param[param.len()..]),
+            };
+            let malformed = || QueryError::Malformed(String::from_utf8_lossy(key).to_string());
+            let key = decode_component(key).ok_or_else(malformed)?;
+            let value = decode_component(value).ok_or_else(malformed)?;
+            params.push((key, value));
+        }
+        Ok(QueryParams { params })
+    }
+
+    ///Devuelve el valor del parametro dado. Si el parametro esta repetido se toma el primero.
+    pub fn get(&self, key: &str) -> Option<&[u8]> {
+        self.params
+            .iter()
+            .find(|(param_key, _)| param_key.as_slice() == key.as_bytes())
+            .map(|(_, value)| value.as_slice())
+    }
+
+    ///Devuelve todos los valores del parametro dado, en el orden en el que llegaron
+    pub fn get_all(&self, key: &str) -> Vec<&[u8]> {
+        self.params
+            .iter()
+            .filter(|(param_key, _)| param_key.as_slice() == key.as_bytes())
+            .map(|(_, value)| value.as_slice())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_params_in_any_order_ok() {
+        let params = QueryParams::parse("left=10&peer_id=abc&port=6881".as_bytes()).unwrap();
+        assert_eq!(Some("10".as_bytes()), params.get("left"));
+        assert_eq!(Some("abc".as_bytes()), params.get("peer_id"));
+        assert_eq!(Some("6881".as_bytes()), params.get("port"));
+        assert_eq!(None, params.get("event"));
+    }
+
+    #[test]
+    fn parse_does_not_match_inside_other_keys_ok() {
+        let params = QueryParams::parse("xpeer_id=bad&peer_id=good&bleft=1".as_bytes()).unwrap();
+        assert_eq!(Some("good".as_bytes()), params.get("peer_id"));
+        assert_eq!(None, params.get("left"));
+    }
+
+    #[test]
+    fn parse_duplicated_params_ok() {
+        let params =
+            QueryParams::parse("info_hash=a&info_hash=b&port=1&port=2".as_bytes()).unwrap();
+        assert_eq!(
+            vec!["a".as_bytes(), "b".as_bytes()],
+            params.get_all("info_hash")
+        );
+        assert_eq!(Some("1".as_bytes()), params.get("port"));
+    }
+
+    #[test]
+    fn parse_decodes_plus_and_percent_ok() {
+        let params = QueryParams::parse("key=a+b%2Bc%20d&empty=&flag".as_bytes()).unwrap();
+        assert_eq!(Some("a b+c d".as_bytes()), params.get("key"));
+        assert_eq!(Some("".as_bytes()), params.get("empty"));
+        assert_eq!(Some("".as_bytes()), params.get("flag"));
+    }
+
+    #[test]
+    fn parse_error_reports_malformed_param() {
+        assert_eq!(
+            Err(QueryError::Malformed("peer_id".to_owned())),
+            QueryParams::parse("port=1&peer_id=%zz".as_bytes())
+        );
+        assert_eq!(
+            Err(QueryError::Malformed("left".to_owned())),
+            QueryParams::parse("left=%4".as_bytes())
+        );
+    }
+}